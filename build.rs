@@ -3,6 +3,7 @@
 fn main() {
     // Enable the following line to compile the resources only in release mode if it slows down the build during development.
     //#[cfg(not(debug_assertions))]
+    #[cfg(feature = "gui")]
     {
         extern crate embed_resource;
         use std::env;
@@ -128,7 +128,7 @@ impl Token {
                 self.buffer.push(c);
             }
             (Ty::Literal, _) => match c {
-                c if c == '(' => {
+                c if c == '(' || (c == ':' && syntax.labels) => {
                     self.ty = Ty::Function;
                     tokens.extend(self.drain(Ty::Punctuation(c)));
                     tokens.extend(self.push_drain(c, Ty::Unknown));
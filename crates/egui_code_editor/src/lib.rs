@@ -106,6 +106,7 @@ pub struct CodeEditor {
     vscroll: bool,
     stick_to_bottom: bool,
     shrink: bool,
+    highlighted_line: Option<usize>,
 }
 
 #[cfg(feature = "editor")]
@@ -131,6 +132,7 @@ impl Default for CodeEditor {
             vscroll: true,
             stick_to_bottom: false,
             shrink: false,
+            highlighted_line: None,
         }
     }
 }
@@ -217,6 +219,17 @@ impl CodeEditor {
         }
     }
 
+    /// Highlight a line, such as the one being executed, and scroll to it when it changes.
+    /// Lines are counted from 0.
+    ///
+    /// **Default: None**
+    pub fn with_highlighted_line(self, highlighted_line: Option<usize>) -> Self {
+        CodeEditor {
+            highlighted_line,
+            ..self
+        }
+    }
+
     #[cfg(feature = "egui")]
     pub fn format(&self, ty: TokenType) -> egui::text::TextFormat {
         let font_id = egui::FontId::monospace(self.fontsize);
@@ -270,6 +283,46 @@ impl CodeEditor {
         );
     }
 
+    #[cfg(feature = "egui")]
+    fn highlight_line(&self, ui: &mut egui::Ui, output: &TextEditOutput) {
+        let id = egui::Id::new(format!("{}_highlighted_line", self.id));
+        let previous = ui
+            .data_mut(|data| data.get_temp::<Option<usize>>(id))
+            .flatten();
+        ui.data_mut(|data| data.insert_temp(id, self.highlighted_line));
+
+        let Some(line) = self.highlighted_line else {
+            return;
+        };
+
+        // Characters before the line, counting each newline
+        let start = output
+            .galley
+            .text()
+            .split('\n')
+            .take(line)
+            .map(|text| text.chars().count() + 1)
+            .sum();
+        let row = output
+            .galley
+            .pos_from_ccursor(egui::text::CCursor::new(start));
+        let top = output.galley_pos.y;
+        let rect = egui::Rect::from_x_y_ranges(
+            output.response.rect.x_range(),
+            top + row.min.y..=top + row.max.y,
+        );
+
+        let color = self
+            .theme
+            .type_color(TokenType::Function)
+            .gamma_multiply(0.25);
+        ui.painter().rect_filled(rect, 0.0, color);
+
+        if previous != Some(line) {
+            ui.scroll_to_rect(rect, Some(egui::Align::Center));
+        }
+    }
+
     #[cfg(feature = "egui")]
     /// Show Code Editor
     pub fn show(&mut self, ui: &mut egui::Ui, text: &mut dyn egui::TextBuffer) -> TextEditOutput {
@@ -295,6 +348,7 @@ impl CodeEditor {
                             .desired_width(if self.shrink { 0.0 } else { f32::MAX })
                             .layouter(&mut layouter)
                             .show(ui);
+                        self.highlight_line(ui, &output);
                         text_edit_output = Some(output);
                     });
             });
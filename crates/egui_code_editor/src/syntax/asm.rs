@@ -1177,6 +1177,7 @@ impl Syntax {
                 "ZMM10", "ZMM11", "ZMM12", "ZMM13", "ZMM14", "ZMM15",
                 // ZMM
             ]),
+            labels: false,
         }
     }
 }
@@ -104,6 +104,8 @@ pub struct Syntax {
     pub keywords: BTreeSet<&'static str>,
     pub types: BTreeSet<&'static str>,
     pub special: BTreeSet<&'static str>,
+    /// Highlight a word followed by a colon as a function, for assembly labels.
+    pub labels: bool,
 }
 impl Default for Syntax {
     fn default() -> Self {
@@ -161,6 +163,9 @@ impl Syntax {
             ..self
         }
     }
+    pub fn with_labels(self, labels: bool) -> Self {
+        Syntax { labels, ..self }
+    }
 
     pub fn language(&self) -> &str {
         self.language
@@ -205,6 +210,7 @@ impl Syntax {
             keywords: BTreeSet::new(),
             types: BTreeSet::new(),
             special: BTreeSet::new(),
+            labels: false,
         }
     }
 }
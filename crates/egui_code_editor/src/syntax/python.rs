@@ -32,6 +32,7 @@ impl Syntax {
                 "frozenset",
             ]),
             special: BTreeSet::from(["False", "None", "True"]),
+            labels: false,
         }
     }
 }
@@ -36,6 +36,7 @@ impl Syntax {
                 "alias", "bg", "cd", "command", "false", "fc", "fg", "getopts", "jobs", "kill",
                 "newgrp", "pwd", "read", "true", "umask", "unalias", "wait",
             ]),
+            labels: false,
         }
     }
 }
@@ -133,6 +133,7 @@ impl Syntax {
                 "DATABASE",
             ]),
             special: BTreeSet::from(["PUBLIC"]),
+            labels: false,
         }
     }
 }
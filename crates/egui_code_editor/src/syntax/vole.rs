@@ -41,6 +41,7 @@ impl Syntax {
                 "re", // 0xE
                 "rf", // 0xF
             ]),
+            labels: true,
         }
     }
 }
@@ -63,3 +63,22 @@ fn simple_rust() {
     println!("{str}");
     assert_eq!(input, output);
 }
+
+#[test]
+fn vole_labels() {
+    let syntax = Syntax::vole();
+    let input = vec![
+        Token::new(TokenType::Function, "start"),
+        Token::new(TokenType::Punctuation(':'), ":"),
+        Token::new(TokenType::Whitespace('\n'), "\n"),
+        Token::new(TokenType::Punctuation('.'), "."),
+        Token::new(TokenType::Function, "loop"),
+        Token::new(TokenType::Punctuation(':'), ":"),
+        Token::new(TokenType::Whitespace(' '), " "),
+        Token::new(TokenType::Punctuation('.'), "."),
+        Token::new(TokenType::Literal, "loop"),
+    ];
+    let str = input.iter().map(|h| h.buffer()).collect::<String>();
+    let output = Token::default().tokens(&syntax, &str);
+    assert_eq!(input, output);
+}
@@ -0,0 +1,14 @@
+//! Any UTF-8 source must assemble or return diagnostics, never panic
+//!
+//! `cargo +nightly fuzz run assemble` from the repository root
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vole_vm::asm::assembler::Assembler;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = Assembler::new().assemble(source.to_string());
+    }
+});
@@ -1,7 +1,17 @@
+use std::ops::Range;
+
+use super::{diagnostic::Diagnostics, source_map::SourceMap, symbols::SymbolTable};
+
 #[derive(Debug, Default)]
 pub struct AssemblerResult {
     rom: Vec<u8>,
     program_counter: u8,
+    // Bytes from data directives, in address order
+    data: Vec<Range<usize>>,
+    // Warnings and notes, a result is only returned when there are no errors
+    diagnostics: Diagnostics,
+    source_map: SourceMap,
+    symbols: SymbolTable,
 }
 
 impl AssemblerResult {
@@ -24,4 +34,44 @@ impl AssemblerResult {
     pub fn rom_mut(&mut self) -> &mut Vec<u8> {
         &mut self.rom
     }
+
+    /// Returns the address ranges holding data rather than instructions
+    pub fn data(&self) -> &[Range<usize>] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut Vec<Range<usize>> {
+        &mut self.data
+    }
+
+    /// Returns true if the byte at the address came from a data directive
+    pub fn is_data(&self, address: usize) -> bool {
+        self.data.iter().any(|range| range.contains(&address))
+    }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub fn diagnostics_mut(&mut self) -> &mut Diagnostics {
+        &mut self.diagnostics
+    }
+
+    /// Returns the statement each address was assembled from
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    pub fn source_map_mut(&mut self) -> &mut SourceMap {
+        &mut self.source_map
+    }
+
+    /// Returns the labels, constants and register aliases of the program
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn symbols_mut(&mut self) -> &mut SymbolTable {
+        &mut self.symbols
+    }
 }
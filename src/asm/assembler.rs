@@ -1,12 +1,64 @@
-use crate::asm::asm_result::AssemblerResult;
+use std::{cell::RefCell, collections::BTreeSet, ops::Range};
+
+use crate::{
+    asm::{
+        asm_result::AssemblerResult,
+        diagnostic::{Diagnostic, Diagnostics},
+        expression::{self, enclosed},
+        macros::{self, ExpandedLine, Expansion},
+        source_map::SourceSpan,
+        symbols::{Symbol, SymbolKind, SymbolTable},
+    },
+    vole::instruction::Instruction,
+};
 
 use super::AssemblerError;
-use std::collections::HashMap;
 
 #[derive(Debug, Default)]
 pub struct Assembler {
     log: String,
     line_number: usize,
+    pass: Pass,
+    symbols: SymbolTable,
+    // Last global label, local labels are scoped under it
+    scope: String,
+    // Address of the current line, `$` in expressions
+    address: usize,
+    // Address set by the last `.org`, instructions are expected a whole number of words from it
+    origin: usize,
+    source: String,
+    // Byte range of each source line
+    lines: Vec<Range<usize>>,
+    // The lines to assemble, with macro calls replaced by their bodies
+    expansion: Expansion,
+    // Index of the current line in the expansion
+    index: usize,
+    // Size of the program after each expanded line of the first pass
+    layout: Vec<usize>,
+    // Expanded lines with an error in the first pass, the second pass leaves them out
+    failed: BTreeSet<usize>,
+    // Diagnostics with the line which reported them
+    diagnostics: Vec<(usize, Diagnostic)>,
+    // Byte range of the current statement, without label or comment
+    statement: Range<usize>,
+    // Symbols found by lookups with the line they were used on, lookups only borrow the assembler
+    references: RefCell<Vec<(String, usize)>>,
+}
+
+// Mnemonics which become a two byte instruction
+pub(super) const INSTRUCTIONS: [&str; 9] = [
+    "ld", "adds", "addf", "or", "and", "xor", "rot", "halt", "jp",
+];
+
+// Every address fits in a byte
+const MEMORY_SIZE: usize = 256;
+
+/// The source is read twice, first to find the address of every label and then to emit the program
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+enum Pass {
+    #[default]
+    Symbols,
+    Emit,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -14,7 +66,6 @@ enum ValueType {
     Register(u8),
     Address(u8),
     Literal(u8),
-    Label(String),
 }
 
 impl Assembler {
@@ -31,309 +82,775 @@ impl Assembler {
         self.log.clone()
     }
 
+    /// Returns the labels, constants and register aliases found by the last call to `assemble`
+    #[must_use]
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Assembly carries on after a line with an error, so every problem in the source is found
+    ///
     /// # Errors
     ///
-    /// Will return `AssemblerError` if an error occurs during assembly
-    pub fn assemble(&mut self, source_code: String) -> Result<AssemblerResult, AssemblerError> {
-        // <Label, Calling Address>
-        let mut labels: HashMap<String, u8> = HashMap::new();
-
-        let mut asm_result = AssemblerResult::new();
-
+    /// Will return every diagnostic, warnings and notes included, if any line has an error
+    pub fn assemble(&mut self, source_code: String) -> Result<AssemblerResult, Diagnostics> {
         let source_lines: Vec<&str> = source_code.split_terminator("\n").collect();
 
         self.add_log("---------------------------");
         self.add_log(&format!("Line count: {}", source_lines.len()));
 
-        for (line_num, line) in source_lines.iter().enumerate() {
+        self.source.clone_from(&source_code);
+        self.symbols = SymbolTable::new();
+        self.diagnostics.clear();
+        self.failed.clear();
+        self.layout.clear();
+
+        let mut start = 0;
+        self.lines = source_lines
+            .iter()
+            .map(|line| {
+                let range = start..start + line.len();
+                start = range.end + 1;
+                range
+            })
+            .collect();
+
+        self.expansion = macros::expand(&source_lines);
+
+        self.pass = Pass::Symbols;
+        self.assemble_lines();
+
+        self.add_log("---------------------------");
+        self.add_log(&format!("Symbols: {}", self.symbols.len()));
+
+        self.pass = Pass::Emit;
+        let mut asm_result = self.assemble_lines();
+
+        for (name, line) in self.references.take() {
+            self.symbols.add_reference(&name, line + 1);
+        }
+
+        // Each pass reports in line order, notes stay after the diagnostic they belong to
+        self.diagnostics.sort_by_key(|(line, _)| *line);
+        let mut diagnostics = Diagnostics::new();
+        for (_, diagnostic) in self.diagnostics.drain(..) {
+            diagnostics.push(diagnostic);
+        }
+
+        self.add_log("---------------------------");
+        if diagnostics.has_errors() {
+            self.add_log("Assembler failed");
+            return Err(diagnostics);
+        }
+        self.add_log("Assembler completed");
+
+        *asm_result.diagnostics_mut() = diagnostics;
+        *asm_result.symbols_mut() = self.symbols.clone();
+        Ok(asm_result)
+    }
+
+    fn assemble_lines(&mut self) -> AssemblerResult {
+        let mut asm_result = AssemblerResult::new();
+        self.scope.clear();
+        self.origin = 0;
+
+        for index in 0..self.expansion.lines.len() {
+            let line = self.expansion.lines[index].clone();
+            let line_num = line.line;
+            self.index = index;
             self.line_number = line_num;
             self.add_log("---------------------------");
-            self.add_log(&format!("{:?}: {}", line_num, line));
+            self.add_log(&format!("{:?}: {}", line_num, line.text));
 
-            // Skip empty lines and comment lines
-            if line.starts_with(";") || line.is_empty() {
-                self.add_log("Skipping empty or comment");
-                continue;
+            // Lines from a macro are part of the statement which called it
+            if let Some(call) = &line.call {
+                let line_start = self.lines.get(line_num).map_or(0, |range| range.start);
+                self.statement = line_start + call.start..line_start + call.end;
             }
 
-            // Trim whitespace and end of line comments
-            let line = line.trim();
-            let line = line.split_once(';').map_or(line, |(before, _)| before);
-            let line = line.trim_end();
-
-            let (pre, post) = match line.split_once(" ") {
-                Some((pre, post)) => (pre, post),
-                None => (line, ""),
+            let result = match line.error {
+                Some(error) => Err(error),
+                None => self.assemble_line(&line.text, &mut asm_result),
             };
+            if let Err(e) = &result {
+                self.add_log(&format!("Error: {e}"));
+            }
 
-            match pre.to_lowercase().as_str() {
-                "ld" => {
-                    let (lhs, rhs) = split_two_args(post);
-                    self.add_log(&format!("lhs_str: {}\nrhs_str: {}", lhs, rhs));
-
-                    let lhs = self.resolve_argument(&lhs)?;
-                    let rhs = self.resolve_argument(&rhs)?;
-                    self.add_log(&format!("lhs: {:?}\nrhs: {:?}", lhs, rhs));
-
-                    match lhs {
-                        ValueType::Register(r0) => match rhs {
-                            ValueType::Register(r1) => {
-                                //0x4RXY
-                                let high = (0x4u8 << 4) | r0;
-                                let low = r1;
-
-                                self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                                asm_result.rom_mut().push(high);
-                                asm_result.rom_mut().push(low);
-                            }
-                            ValueType::Address(a) => {
-                                //0x1RXY
-                                let high = (0x1u8 << 4) | r0;
-                                let low = a;
-
-                                self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                                asm_result.rom_mut().push(high);
-                                asm_result.rom_mut().push(low);
-                            }
-                            ValueType::Literal(l) => {
-                                //0x2RXY
-                                let high = (0x2u8 << 4) | r0;
-                                let low = l;
-
-                                self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                                asm_result.rom_mut().push(high);
-                                asm_result.rom_mut().push(low);
-                            }
-                            ValueType::Label(l) => {
-                                return Err(AssemblerError::TypeMismatch(line_num, l))
-                            }
-                        },
-                        ValueType::Address(a) => {
-                            //0x3RXY
-                            match rhs {
-                                ValueType::Register(r) => {
-                                    let high = (0x3u8 << 4) | r;
-                                    let low = a;
-
-                                    self.add_log(&format!(
-                                        "Pushing: {:#04X?}, {:#04X?}",
-                                        high, low
-                                    ));
-                                    asm_result.rom_mut().push(high);
-                                    asm_result.rom_mut().push(low);
-                                }
-                                _ => {
-                                    return Err(AssemblerError::TypeMismatch(
-                                        line_num,
-                                        "non-register value".to_owned(),
-                                    ));
-                                }
-                            };
-                        }
-                        _ => {
-                            return Err(AssemblerError::LoadOpFail(
-                                line_num,
-                                "Failed to determine ld type".to_owned(),
-                            ));
-                        }
-                    };
+            match self.pass {
+                Pass::Symbols => {
+                    // Only the line which runs past the end of memory is reported
+                    let end = asm_result.rom().len();
+                    let result = result.and_then(|()| match self.layout.last() {
+                        Some(&previous) if previous > MEMORY_SIZE => Ok(()),
+                        _ if end > MEMORY_SIZE => Err(AssemblerError::ProgramTooLarge(
+                            line_num,
+                            strip_comment(line.text.trim()).trim_end().to_string(),
+                        )),
+                        _ => Ok(()),
+                    });
+
+                    if let Err(e) = result {
+                        self.failed.insert(index);
+                        self.report(e);
+                    }
+                    self.layout.push(asm_result.rom().len());
+                }
+                Pass::Emit => {
+                    let failed = self.failed.contains(&index);
+                    if failed || result.is_err() {
+                        // Keep the first pass layout so the addresses of later lines still match
+                        asm_result.rom_mut().resize(self.layout[index], 0x00);
+                    }
+                    // Lines which failed the first pass have been reported already
+                    if let Err(e) = result
+                        && !failed
+                    {
+                        self.report(e);
+                    }
                 }
-                "adds" => {
-                    //0x5RST
-                    let (r, s, t) = self.resolve_rst(post)?;
+            }
+        }
 
-                    let high = (0x5u8 << 4) | r;
-                    let low = (s << 4) | t;
+        if !asm_result.rom().len().is_multiple_of(2) {
+            asm_result.rom_mut().push(0x00);
+        }
 
-                    self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                    asm_result.rom_mut().push(high);
-                    asm_result.rom_mut().push(low);
-                }
-                "addf" => {
-                    //0x6RST
+        asm_result
+    }
 
-                    let (r, s, t) = self.resolve_rst(post)?;
+    fn assemble_line(
+        &mut self,
+        line: &str,
+        asm_result: &mut AssemblerResult,
+    ) -> Result<(), AssemblerError> {
+        let source_line = line;
+
+        // Skip empty lines and comment lines
+        if line.starts_with(";") || line.is_empty() {
+            self.add_log("Skipping empty or comment");
+            return Ok(());
+        }
 
-                    let high = (0x6u8 << 4) | r;
-                    let low = (s << 4) | t;
+        // Trim whitespace and end of line comments
+        let line = line.trim();
+        let line = strip_comment(line);
+        let line = line.trim_end();
 
-                    self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                    asm_result.rom_mut().push(high);
-                    asm_result.rom_mut().push(low);
-                }
-                "or" => {
-                    //0x7RST
-                    let (r, s, t) = self.resolve_rst(post)?;
+        self.address = asm_result.rom().len();
 
-                    let high = (0x7u8 << 4) | r;
-                    let low = (s << 4) | t;
+        // A label can share its line with an instruction
+        let (first, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let (label, statement) = match first.strip_suffix(':') {
+            Some(label) => (Some(label), rest.trim_start()),
+            None => (None, line),
+        };
 
-                    self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                    asm_result.rom_mut().push(high);
-                    asm_result.rom_mut().push(low);
-                }
-                "and" => {
-                    //0x8RST
-                    let (r, s, t) = self.resolve_rst(post)?;
+        // The statement ends the line, after any indentation and label
+        if self.expanded_line().call.is_none() {
+            let line_start = self
+                .lines
+                .get(self.line_number)
+                .map_or(0, |range| range.start);
+            let indent = source_line.len() - source_line.trim_start().len();
+            let start = line_start + indent + line.len() - statement.len();
+            self.statement = start..start + statement.len();
+        }
 
-                    let high = (0x8u8 << 4) | r;
-                    let low = (s << 4) | t;
+        // The label points at the next instruction, which is still laid out if the label is bad
+        let label = match label {
+            Some(label) => self.define_label(label, self.address as i64),
+            None => Ok(()),
+        };
+        let statement = self.assemble_statement(statement, asm_result);
+        label.and(statement)
+    }
 
-                    self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                    asm_result.rom_mut().push(high);
-                    asm_result.rom_mut().push(low);
-                }
-                "xor" => {
-                    //0x9RST
-                    let (r, s, t) = self.resolve_rst(post)?;
+    /// An instruction or directive, without a label or comment
+    fn assemble_statement(
+        &mut self,
+        line: &str,
+        asm_result: &mut AssemblerResult,
+    ) -> Result<(), AssemblerError> {
+        if line.is_empty() {
+            return Ok(());
+        }
 
-                    let high = (0x9u8 << 4) | r;
-                    let low = (s << 4) | t;
+        let (pre, post) = match line.split_once(char::is_whitespace) {
+            Some((pre, post)) => (pre, post),
+            None => (line, ""),
+        };
 
-                    self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                    asm_result.rom_mut().push(high);
-                    asm_result.rom_mut().push(low);
-                }
-                "rot" => {
-                    //0xAR0X
-                    let (lhs, rhs) = split_two_args(post);
-                    self.add_log(&format!("lhs_str: {}\nrhs_str: {}", lhs, rhs));
-
-                    let lhs = match self.resolve_argument(&lhs) {
-                        Ok(v) => match v {
-                            ValueType::Register(r) => r,
-                            _ => {
-                                return Err(AssemblerError::TypeMismatch(
-                                    line_num,
-                                    "non-register".to_string(),
-                                ));
-                            }
-                        },
-                        Err(e) => {
-                            return Err(e);
+        let mnemonic = pre.to_lowercase();
+
+        // Instructions are always two bytes, the operands are resolved once every symbol is known
+        if self.pass == Pass::Symbols && INSTRUCTIONS.contains(&mnemonic.as_str()) {
+            asm_result.rom_mut().extend_from_slice(&[0x00, 0x00]);
+            return Ok(());
+        }
+
+        match mnemonic.as_str() {
+            "ld" => {
+                let [lhs, rhs] = self.split_args(line, post)?;
+                self.add_log(&format!("lhs_str: {}\nrhs_str: {}", lhs, rhs));
+
+                let lhs = self.resolve_argument(&lhs)?;
+                let rhs = self.resolve_argument(&rhs)?;
+                self.add_log(&format!("lhs: {:?}\nrhs: {:?}", lhs, rhs));
+
+                match lhs {
+                    ValueType::Register(r0) => match rhs {
+                        ValueType::Register(r1) => {
+                            //0x40RS
+                            self.push_instruction(
+                                asm_result,
+                                Instruction::Move { src: r1, dest: r0 },
+                            );
                         }
-                    };
-                    let rhs = match self.resolve_argument(&rhs) {
-                        Ok(v) => match v {
-                            ValueType::Literal(l) => l,
+                        ValueType::Address(a) => {
+                            //0x1RXY
+                            self.push_instruction(
+                                asm_result,
+                                Instruction::Load { r: r0, address: a },
+                            );
+                        }
+                        ValueType::Literal(l) => {
+                            //0x2RXY
+                            self.push_instruction(
+                                asm_result,
+                                Instruction::LoadImm { r: r0, value: l },
+                            );
+                        }
+                    },
+                    ValueType::Address(a) => {
+                        //0x3RXY
+                        match rhs {
+                            ValueType::Register(r) => {
+                                self.push_instruction(
+                                    asm_result,
+                                    Instruction::Store { r, address: a },
+                                );
+                            }
                             _ => {
                                 return Err(AssemblerError::TypeMismatch(
-                                    line_num,
-                                    "non-literal".to_string(),
+                                    self.line_number,
+                                    "non-register value".to_owned(),
                                 ));
                             }
-                        },
-                        Err(e) => {
-                            return Err(e);
+                        };
+                    }
+                    _ => {
+                        return Err(AssemblerError::LoadOpFail(
+                            self.line_number,
+                            "Failed to determine ld type".to_owned(),
+                        ));
+                    }
+                };
+            }
+            "adds" => {
+                //0x5RST
+                let (r, s, t) = self.resolve_rst(line, post)?;
+                self.push_instruction(asm_result, Instruction::AddS { r, s, t });
+            }
+            "addf" => {
+                //0x6RST
+                let (r, s, t) = self.resolve_rst(line, post)?;
+                self.push_instruction(asm_result, Instruction::AddF { r, s, t });
+            }
+            "or" => {
+                //0x7RST
+                let (r, s, t) = self.resolve_rst(line, post)?;
+                self.push_instruction(asm_result, Instruction::Or { r, s, t });
+            }
+            "and" => {
+                //0x8RST
+                let (r, s, t) = self.resolve_rst(line, post)?;
+                self.push_instruction(asm_result, Instruction::And { r, s, t });
+            }
+            "xor" => {
+                //0x9RST
+                let (r, s, t) = self.resolve_rst(line, post)?;
+                self.push_instruction(asm_result, Instruction::Xor { r, s, t });
+            }
+            "rot" => {
+                //0xAR0X
+                let [lhs, rhs] = self.split_args(line, post)?;
+                self.add_log(&format!("lhs_str: {}\nrhs_str: {}", lhs, rhs));
+
+                let lhs = match self.resolve_argument(&lhs) {
+                    Ok(v) => match v {
+                        ValueType::Register(r) => r,
+                        _ => {
+                            return Err(AssemblerError::TypeMismatch(
+                                self.line_number,
+                                "non-register".to_string(),
+                            ));
                         }
-                    };
-                    self.add_log(&format!("lhs: {:?}\nrhs: {:?}", lhs, rhs));
-
-                    let high = (0xAu8 << 4) | lhs;
-                    let low = rhs;
+                    },
+                    Err(e) => {
+                        return Err(e);
+                    }
+                };
+                let rhs = match self.resolve_argument(&rhs) {
+                    Ok(v) => match v {
+                        ValueType::Literal(l) if l <= 0xF => l,
+                        ValueType::Literal(_) => {
+                            return Err(AssemblerError::ValueOutOfRange(self.line_number, rhs));
+                        }
+                        _ => {
+                            return Err(AssemblerError::TypeMismatch(
+                                self.line_number,
+                                "non-literal".to_string(),
+                            ));
+                        }
+                    },
+                    Err(e) => {
+                        return Err(e);
+                    }
+                };
+                self.add_log(&format!("lhs: {:?}\nrhs: {:?}", lhs, rhs));
 
-                    self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                    asm_result.rom_mut().push(high);
-                    asm_result.rom_mut().push(low);
-                }
-                "halt" => {
-                    asm_result.rom_mut().push(0xC0);
-                    asm_result.rom_mut().push(0x00);
-                    self.add_log("Pushing 0xC0, 0x00");
-                }
-                "jp" => {
-                    // TODO: Handle labels
-                    // TODO: Add support for multiple jump instructions to the same label
-                    //0xBRXY
-                    let (lhs, rhs) = split_two_args(post);
-                    self.add_log(&format!("lhs_str: {}\nrhs_str: {}", lhs, rhs));
-
-                    let lhs = match self.resolve_argument(&lhs) {
-                        Ok(v) => match v {
-                            ValueType::Register(r) => r,
-                            _ => {
-                                return Err(AssemblerError::TypeMismatch(
-                                    line_num,
-                                    "non-register".to_string(),
-                                ));
-                            }
-                        },
-                        Err(e) => {
-                            return Err(e);
+                self.push_instruction(asm_result, Instruction::Rot { r: lhs, x: rhs });
+            }
+            "halt" => {
+                self.split_args::<0>(line, post)?;
+                self.push_instruction(asm_result, Instruction::Halt);
+            }
+            "jp" => {
+                //0xBRXY
+                let [lhs, rhs] = self.split_args(line, post)?;
+                self.add_log(&format!("lhs_str: {}\nrhs_str: {}", lhs, rhs));
+
+                let lhs = match self.resolve_argument(&lhs) {
+                    Ok(v) => match v {
+                        ValueType::Register(r) => r,
+                        _ => {
+                            return Err(AssemblerError::TypeMismatch(
+                                self.line_number,
+                                "non-register".to_string(),
+                            ));
                         }
+                    },
+                    Err(e) => {
+                        return Err(e);
+                    }
+                };
+                let rhs = self.resolve_address(&rhs)?;
+                self.add_log(&format!("lhs: {:?}\nrhs: {:?}", lhs, rhs));
+
+                self.push_instruction(
+                    asm_result,
+                    Instruction::Jump {
+                        r: lhs,
+                        address: rhs,
+                    },
+                );
+            }
+            ".org" => {
+                *asm_result.program_counter_mut() = self.resolve_address(post)?;
+                self.origin = usize::from(asm_result.program_counter());
+
+                let new_size = asm_result.program_counter() as usize;
+                if new_size < asm_result.rom().len() {
+                    self.warn(format!(
+                        "'.org' moves back from {:#04X} to {:#04X}, the bytes between are dropped",
+                        asm_result.rom().len(),
+                        new_size
+                    ));
+                }
+                asm_result.rom_mut().resize(new_size, 0x00);
+                asm_result.source_map_mut().truncate(new_size);
+            }
+            ".db" | ".byte" => {
+                let mut bytes = Vec::new();
+                for arg in post.split(',') {
+                    // Values can be labels later in the source
+                    let value = match self.pass {
+                        Pass::Symbols => 0x00,
+                        Pass::Emit => self.resolve_byte(arg.trim())?,
                     };
+                    bytes.push(value);
+                }
+                self.push_data(asm_result, &bytes);
+            }
+            ".fill" => {
+                let (count, value) = post.split_once(',').unwrap_or((post, ""));
+                let count = self.resolve_address(count.trim())?;
+                // The count places the labels after it, the value can be a label later in the source
+                let value = match (self.pass, value.trim()) {
+                    (Pass::Symbols, _) | (Pass::Emit, "") => 0x00,
+                    (Pass::Emit, value) => self.resolve_byte(value)?,
+                };
+                self.push_data(asm_result, &vec![value; count as usize]);
+            }
+            ".space" => {
+                let count = self.resolve_address(post.trim())?;
+                self.push_data(asm_result, &vec![0x00; count as usize]);
+            }
+            ".equ" => {
+                let (name, value) = split_definition(post);
+                let value = self.evaluate(value)?;
+                self.define_symbol(name, value, SymbolKind::Constant)?;
+            }
+            ".reg" => {
+                let (name, register) = split_definition(post);
+                let register = match self.resolve_argument(register)? {
+                    ValueType::Register(r) => r,
+                    _ => {
+                        return Err(AssemblerError::TypeMismatch(
+                            self.line_number,
+                            register.to_string(),
+                        ));
+                    }
+                };
+                self.define_symbol(name, register.into(), SymbolKind::Register)?;
+            }
+            ".ascii" => {
+                let bytes = self.parse_ascii(post.trim())?;
+                self.push_data(asm_result, &bytes);
+            }
+            _ => {
+                return Err(AssemblerError::UnknownMnemonic(
+                    self.line_number,
+                    pre.to_string(),
+                ));
+            }
+        }
 
-                    let high = (0xBu8 << 4) | lhs;
-                    let low = 0xFF;
+        Ok(())
+    }
 
-                    self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
-                    asm_result.rom_mut().push(high);
-                    asm_result.rom_mut().push(low);
+    /// Adds an error for the current line, a duplicate definition gets a note pointing at the first
+    fn report(&mut self, error: AssemblerError) {
+        let span = self.line_span(error.text());
+        let notes = self.expansion_notes(error.text());
+        let first = match &error {
+            AssemblerError::DuplicateSymbol(_, name) => self.symbols.get(name).map(|s| s.line - 1),
+            AssemblerError::DuplicateMacro(_, name) => {
+                self.expansion.definitions.get(name).copied()
+            }
+            _ => None,
+        };
 
-                    let call_address = asm_result.rom().len() - 1;
-                    labels.insert(rhs, call_address as u8);
-                    self.add_log(&format!("Label call address: {}", call_address));
-                }
-                ".org" => {
-                    *asm_result.program_counter_mut() = match self.resolve_argument(post) {
-                        Ok(result) => match result {
-                            ValueType::Literal(v) => v,
-                            _ => {
-                                return Err(AssemblerError::TypeMismatch(
-                                    line_num,
-                                    "non-literal".to_string(),
-                                ));
-                            }
-                        },
-                        Err(e) => {
-                            return Err(e);
-                        }
-                    };
+        self.diagnostics
+            .push((self.line_number, Diagnostic::error(error, span)));
+        for note in notes {
+            self.diagnostics.push((self.line_number, note));
+        }
 
-                    let new_size = asm_result.program_counter() as usize;
-                    asm_result.rom_mut().resize(new_size, 0x00);
-                }
-                unknown => {
-                    if unknown.trim_end().ends_with(":") {
-                        let label = unknown.trim_end_matches(":");
-
-                        if labels.contains_key(label) {
-                            // TODO: Check for unresolved labels
-                            match labels.remove_entry(label) {
-                                Some((_k, call)) => {
-                                    // The target jump address will be the next line
-                                    let target = asm_result.rom().len() as u8;
-                                    asm_result.rom_mut()[call as usize] = target;
-
-                                    self.add_log(&format!("Storing jump target {:#04X?}", target));
-                                }
-                                None => {
-                                    return Err(AssemblerError::LabelResolution(
-                                        line_num,
-                                        label.to_string(),
-                                    ))
-                                }
-                            }
-                        }
-                    } else {
-                        eprintln!("Unknown mnemonic: {}", pre);
-                    }
-                }
+        if let Some(line) = first {
+            let note =
+                Diagnostic::note(line, self.span(line, ""), "First defined here".to_string());
+            self.diagnostics.push((self.line_number, note));
+        }
+    }
+
+    /// Adds a warning for the current line, only reported in the second pass
+    fn warn(&mut self, message: String) {
+        if self.pass == Pass::Emit {
+            let warning = Diagnostic::warning(self.line_number, self.line_span(""), message);
+            self.diagnostics.push((self.line_number, warning));
+            for note in self.expansion_notes("") {
+                self.diagnostics.push((self.line_number, note));
             }
         }
+    }
 
-        if asm_result.rom().len() % 2 != 0 {
-            asm_result.rom_mut().push(0x00);
+    fn expanded_line(&self) -> &ExpandedLine {
+        &self.expansion.lines[self.index]
+    }
+
+    /// Finds the text in the current line, a line from a macro points at the call instead
+    fn line_span(&self, text: &str) -> Range<usize> {
+        let span = self.span(self.line_number, text);
+        if self.expanded_line().call.is_none()
+            || (self.statement.start <= span.start && span.end <= self.statement.end)
+        {
+            span
+        } else {
+            self.statement.clone()
         }
+    }
 
-        self.add_log("---------------------------");
-        self.add_log("Assembler completed");
+    /// A note on each macro line the current line was expanded from, innermost first
+    fn expansion_notes(&self, text: &str) -> Vec<Diagnostic> {
+        let expansion = &self.expanded_line().expansion;
 
-        Ok(asm_result)
+        let mut notes = Vec::new();
+        let mut seen = BTreeSet::new();
+        for (depth, (name, line)) in expansion.iter().enumerate().rev() {
+            // A recursive macro reaches the same line again and again
+            if !seen.insert((name, line)) {
+                continue;
+            }
+
+            let caller = match depth {
+                0 => self.line_number,
+                _ => expansion[depth - 1].1,
+            };
+            // The text is only looked for in the innermost line, outer lines are calls
+            let text = if depth + 1 == expansion.len() {
+                text
+            } else {
+                ""
+            };
+            notes.push(Diagnostic::note(
+                *line,
+                self.span(*line, text),
+                format!("In macro '{name}' called on line {}", caller + 1),
+            ));
+        }
+
+        notes
+    }
+
+    /// Finds the text in the line, or the whole line without indentation and comment
+    fn span(&self, line: usize, text: &str) -> Range<usize> {
+        let Some(range) = self.lines.get(line) else {
+            return 0..0;
+        };
+        let source = &self.source[range.clone()];
+        let code = strip_comment(source).trim_end();
+
+        let found = Some(text).filter(|text| !text.is_empty()).and_then(|text| {
+            code.find(text)
+                .or_else(|| code.to_ascii_lowercase().find(&text.to_ascii_lowercase()))
+        });
+
+        let (start, length) = match found {
+            Some(start) => (start, text.len()),
+            None => {
+                let start = code.len() - code.trim_start().len();
+                (start, code.len() - start)
+            }
+        };
+
+        range.start + start..range.start + start + length
+    }
+
+    /// Labels are added to the symbol table in the first pass, the second pass finds them again
+    fn define_label(&mut self, label: &str, address: i64) -> Result<(), AssemblerError> {
+        let name = match label.strip_prefix('.') {
+            Some(local) if is_identifier(local) => self.local_label(local),
+            None if self.is_name(label) => {
+                // Local labels which follow belong to this label
+                self.scope = label.to_string();
+                label.to_string()
+            }
+            _ => {
+                return Err(AssemblerError::InvalidName(
+                    self.line_number,
+                    label.to_string(),
+                ));
+            }
+        };
+
+        self.define_symbol(&name, address, SymbolKind::Label)
+    }
+
+    /// Symbols are defined in the first pass, the second pass finds them again
+    fn define_symbol(
+        &mut self,
+        name: &str,
+        value: i64,
+        kind: SymbolKind,
+    ) -> Result<(), AssemblerError> {
+        // Label names are checked by define_label, local labels contain a dot
+        if kind != SymbolKind::Label && !self.is_name(name) {
+            return Err(AssemblerError::InvalidName(
+                self.line_number,
+                name.to_string(),
+            ));
+        }
+
+        if self.pass == Pass::Emit {
+            return Ok(());
+        }
+
+        self.add_log(&format!("{kind:?} {name} = {value:#04X?}"));
+        let symbol = Symbol {
+            value,
+            line: self.line_number + 1,
+            kind,
+        };
+        self.symbols
+            .define(name, symbol)
+            .map_err(|_| AssemblerError::DuplicateSymbol(self.line_number, name.to_string()))
+    }
+
+    /// Register names can't be reused as symbols
+    fn is_name(&self, name: &str) -> bool {
+        is_identifier(name) && self.register_to_value(&name.to_lowercase()).is_err()
+    }
+
+    /// Local labels are stored as `global.local`
+    fn local_label(&self, local: &str) -> String {
+        format!("{}.{local}", self.scope)
+    }
+
+    /// Finds a symbol, a local label, or a local label of another scope as `global.local`
+    ///
+    /// Returns `None` if the argument isn't a name.
+    fn lookup(&self, arg: &str) -> Option<Result<Symbol, AssemblerError>> {
+        let name = match arg.split_once('.') {
+            None if is_identifier(arg) => arg.to_string(),
+            Some(("", local)) if is_identifier(local) => self.local_label(local),
+            Some((scope, local)) if is_identifier(scope) && is_identifier(local) => arg.to_string(),
+            _ => return None,
+        };
+
+        let symbol = self
+            .symbols
+            .get(&name)
+            .copied()
+            .ok_or_else(|| AssemblerError::UndefinedSymbol(self.line_number, arg.to_string()));
+        if symbol.is_ok() {
+            self.references.borrow_mut().push((name, self.line_number));
+        }
+        Some(symbol)
+    }
+
+    /// Evaluates an expression, names are labels or constants
+    fn evaluate(&self, text: &str) -> Result<i64, AssemblerError> {
+        let resolve = |name: &str| {
+            if name == "$" {
+                return Ok(self.address as i64);
+            }
+
+            let register = self.register_to_value(&name.to_lowercase()).is_ok();
+            match self.lookup(name) {
+                Some(Ok(Symbol {
+                    kind: SymbolKind::Register,
+                    ..
+                })) => Err(AssemblerError::TypeMismatch(
+                    self.line_number,
+                    name.to_string(),
+                )),
+                Some(Ok(symbol)) => Ok(symbol.value),
+                _ if register => Err(AssemblerError::TypeMismatch(
+                    self.line_number,
+                    name.to_string(),
+                )),
+                Some(Err(e)) => Err(e),
+                None => Err(AssemblerError::MalformedExpression(
+                    self.line_number,
+                    text.to_string(),
+                )),
+            }
+        };
+
+        expression::evaluate(text, self.line_number, &resolve)
+    }
+
+    /// Jump targets, counts and memory addresses are 0 to 255
+    fn resolve_address(&self, text: &str) -> Result<u8, AssemblerError> {
+        let value = self.evaluate(text)?;
+        u8::try_from(value)
+            .map_err(|_| AssemblerError::ValueOutOfRange(self.line_number, text.to_string()))
+    }
+
+    /// Bytes are -128 to 255, negative numbers are stored as two's complement
+    fn resolve_byte(&self, text: &str) -> Result<u8, AssemblerError> {
+        let value = self.evaluate(text)?;
+        match value {
+            -128..=-1 => Ok(value as i8 as u8),
+            0..=255 => Ok(value as u8),
+            _ => Err(AssemblerError::ValueOutOfRange(
+                self.line_number,
+                text.to_string(),
+            )),
+        }
+    }
+
+    fn push_instruction(&mut self, asm_result: &mut AssemblerResult, instruction: Instruction) {
+        let [high, low] = instruction.to_bytes();
+
+        // Code placed by `.org` can start anywhere, data with an odd length shifts what follows
+        let address = asm_result.rom().len();
+        if !(address - self.origin).is_multiple_of(2) {
+            self.warn(format!(
+                "Instruction at {address:#04X} isn't aligned with the code before it, \
+                 the data in between has an odd number of bytes"
+            ));
+        }
+
+        self.add_log(&format!("Pushing: {:#04X?}, {:#04X?}", high, low));
+        asm_result.rom_mut().push(high);
+        asm_result.rom_mut().push(low);
+
+        let span = self.statement_span();
+        asm_result
+            .source_map_mut()
+            .insert(address..address + 2, span);
     }
 
-    fn resolve_rst(&self, arg: &str) -> Result<(u8, u8, u8), AssemblerError> {
-        let (r, s, t) = split_three_args(arg);
+    /// Data is marked in the result, next to any data right before it
+    fn push_data(&mut self, asm_result: &mut AssemblerResult, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+
+        self.add_log(&format!("Pushing data: {:#04X?}", bytes));
+        let start = asm_result.rom().len();
+        asm_result.rom_mut().extend_from_slice(bytes);
+        let end = asm_result.rom().len();
+
+        match asm_result.data_mut().last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => asm_result.data_mut().push(start..end),
+        }
+
+        let span = self.statement_span();
+        asm_result.source_map_mut().insert(start..end, span);
+    }
+
+    /// The current statement, for the source map
+    fn statement_span(&self) -> SourceSpan {
+        let line_start = self
+            .lines
+            .get(self.line_number)
+            .map_or(0, |range| range.start);
+        let column = self
+            .source
+            .get(line_start..self.statement.start)
+            .map_or(0, |text| text.chars().count());
+
+        SourceSpan::new(self.line_number, column, self.statement.clone())
+    }
+
+    /// A quoted string of ASCII characters, with `\n`, `\t`, `\r`, `\0`, `\\` and `\"` escapes
+    fn parse_ascii(&self, arg: &str) -> Result<Vec<u8>, AssemblerError> {
+        let malformed = || AssemblerError::MalformedString(self.line_number, arg.to_string());
+
+        let text = arg
+            .strip_prefix('"')
+            .and_then(|text| text.strip_suffix('"'))
+            .ok_or_else(malformed)?;
+
+        let mut bytes = Vec::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            let c = match c {
+                '\\' => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some('\\') => '\\',
+                    Some('"') => '"',
+                    _ => return Err(malformed()),
+                },
+                '"' => return Err(malformed()),
+                c if c.is_ascii() => c,
+                _ => return Err(malformed()),
+            };
+            bytes.push(c as u8);
+        }
+
+        Ok(bytes)
+    }
+
+    fn resolve_rst(&self, statement: &str, args: &str) -> Result<(u8, u8, u8), AssemblerError> {
+        let [r, s, t] = self.split_args(statement, args)?;
 
         let r = match self.resolve_argument(&r) {
             Ok(v) => match v {
@@ -383,6 +900,28 @@ impl Assembler {
         Ok((r, s, t))
     }
 
+    /// Splits comma separated arguments, there must be exactly `N` and none of them empty
+    fn split_args<const N: usize>(
+        &self,
+        statement: &str,
+        args: &str,
+    ) -> Result<[String; N], AssemblerError> {
+        let args: Vec<String> = match args.trim() {
+            "" => Vec::new(),
+            args => args.split(',').map(|arg| arg.trim().to_string()).collect(),
+        };
+
+        if args.iter().any(String::is_empty) {
+            return Err(AssemblerError::ArgumentCount(
+                self.line_number,
+                statement.to_string(),
+            ));
+        }
+
+        args.try_into()
+            .map_err(|_| AssemblerError::ArgumentCount(self.line_number, statement.to_string()))
+    }
+
     fn register_to_value(&self, reg: &str) -> Result<u8, AssemblerError> {
         match reg {
             "r0" => Ok(0x0),
@@ -410,101 +949,84 @@ impl Assembler {
 
     fn resolve_argument(&self, arg: &str) -> Result<ValueType, AssemblerError> {
         let val = arg.to_lowercase();
-        if val.starts_with('r') {
-            // Register
-            match self.register_to_value(val.as_str()) {
-                Ok(v) => {
-                    return Ok(ValueType::Register(v));
-                }
-                Err(e) => return Err(e),
-            }
+        if let Ok(r) = self.register_to_value(val.as_str()) {
+            return Ok(ValueType::Register(r));
         }
 
-        if val.starts_with('(') && val.ends_with(')') {
-            // Memory address
-            match self.numeric_to_value(val.as_str()) {
-                Ok(v) => {
-                    return Ok(ValueType::Address(v));
-                }
-                Err(e) => {
-                    return Err(e);
-                }
-            }
-        } else if val.starts_with('(') || val.ends_with(')') {
-            return Err(AssemblerError::MalformedAddress(self.line_number, val));
+        let alias = |name: &str| match self.lookup(name) {
+            Some(Ok(Symbol {
+                kind: SymbolKind::Register,
+                value,
+                ..
+            })) => Some(value as u8),
+            _ => None,
+        };
+
+        if let Some(r) = alias(arg) {
+            return Ok(ValueType::Register(r));
         }
 
-        if val.starts_with("0x") || val.starts_with("0b") {
-            // Literal
-            match self.numeric_to_value(val.as_str()) {
-                Ok(v) => {
-                    return Ok(ValueType::Literal(v));
-                }
-                Err(e) => {
-                    return Err(e);
-                }
+        if let Some(inner) = enclosed(arg) {
+            // Memory address
+            let inner = inner.trim();
+            if self.register_to_value(&inner.to_lowercase()).is_ok() || alias(inner).is_some() {
+                return Err(AssemblerError::MalformedAddress(self.line_number, val));
             }
-        }
 
-        if val.trim_end().ends_with(':') {
-            return Ok(ValueType::Label(val.trim_end_matches(':').to_string()));
+            return Ok(ValueType::Address(self.resolve_address(inner)?));
         }
 
-        //TODO: labels
-        //Ok(ValueType::Literal(0x0))
-        Err(AssemblerError::UnknownArgument(self.line_number, val))
+        match self.resolve_byte(arg) {
+            Ok(value) => Ok(ValueType::Literal(value)),
+            // Anything shaped like a register which isn't a symbol is a typo
+            Err(AssemblerError::UndefinedSymbol(..)) if val.len() == 2 && val.starts_with('r') => {
+                Err(AssemblerError::UnknownRegister(self.line_number, val))
+            }
+            Err(e) => Err(e),
+        }
     }
+}
 
-    fn numeric_to_value(&self, num: &str) -> Result<u8, AssemblerError> {
-        let value = num.trim_start_matches('(').trim_end_matches(')');
-
-        let radix = if value.starts_with("0x") {
-            16
-        } else if value.starts_with("0b") {
-            2
-        } else {
-            return Err(AssemblerError::MalformedNumber(
-                self.line_number,
-                num.to_string(),
-            ));
-        };
-
-        let prefix = if value.starts_with("0x") {
-            "0x"
-        } else if value.starts_with("0b") {
-            "0b"
-        } else {
-            return Err(AssemblerError::MalformedNumber(
-                self.line_number,
-                num.to_string(),
-            ));
-        };
-
-        let value = value.strip_prefix(prefix).unwrap_or(value);
-        let value = u8::from_str_radix(value, radix).unwrap_or_default();
-
-        Ok(value)
+/// Splits `name value` or `name, value`
+fn split_definition(args: &str) -> (&str, &str) {
+    let args = args.trim();
+    match args.split_once(|c: char| c.is_whitespace() || c == ',') {
+        Some((name, value)) => (name, value.trim_start_matches([',', ' ', '\t']).trim()),
+        None => (args, ""),
     }
 }
 
-fn split_two_args(args: &str) -> (String, String) {
-    let result: Vec<&str> = args.split(',').flat_map(|s| s.split(", ")).collect();
-    (result[0].trim().to_string(), result[1].trim().to_string())
+/// Removes an end of line comment, semicolons inside quotes are kept
+pub(super) fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
 }
 
-fn split_three_args(args: &str) -> (String, String, String) {
-    let result: Vec<&str> = args.split(',').flat_map(|s| s.split(", ")).collect();
-    (
-        result[0].trim().to_string(),
-        result[1].trim().to_string(),
-        result[2].trim().to_string(),
-    )
+pub(super) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::asm::{DEMO_ROM, DEMO_SOURCE};
+    use crate::asm::{DEMO_ROM, DEMO_SOURCE, diagnostic::Severity};
+    use proptest::prelude::*;
     use rand::{self, Rng};
 
     #[test]
@@ -579,40 +1101,49 @@ mod tests {
 
     #[test]
     fn split_args_two() {
-        let (l, r) = split_two_args("a,b");
-        assert_eq!(l, "a");
-        assert_eq!(r, "b");
-
-        let (l, r) = split_two_args("a, b");
-        assert_eq!(l, "a");
-        assert_eq!(r, "b");
-
-        let (l, r) = split_two_args("a , b");
-        assert_eq!(l, "a");
-        assert_eq!(r, "b");
+        let asm = Assembler::new();
+        for args in ["a,b", "a, b", "a , b", " a ,b "] {
+            assert_eq!(asm.split_args("ld", args).unwrap(), ["a", "b"], "{args}");
+        }
     }
 
     #[test]
     fn split_args_three() {
-        let (l, m, r) = split_three_args("a,b,c");
-        assert_eq!(l, "a");
-        assert_eq!(m, "b");
-        assert_eq!(r, "c");
+        let asm = Assembler::new();
+        for args in ["a,b,c", "a, b, c", "a, b,c", "a,b, c"] {
+            assert_eq!(
+                asm.split_args("or", args).unwrap(),
+                ["a", "b", "c"],
+                "{args}"
+            );
+        }
+    }
 
-        let (l, m, r) = split_three_args("a, b, c");
-        assert_eq!(l, "a");
-        assert_eq!(m, "b");
-        assert_eq!(r, "c");
+    #[test]
+    fn argument_counts() {
+        let mut asm = Assembler::new();
 
-        let (l, m, r) = split_three_args("a, b,c");
-        assert_eq!(l, "a");
-        assert_eq!(m, "b");
-        assert_eq!(r, "c");
+        for source in [
+            "ld r0",
+            "ld",
+            "ld r0, 0x01, 0x02",
+            "ld r0,",
+            "ld , r0",
+            "adds r1, r2",
+            "xor r1, r2, r3, r4",
+            "rot r1",
+            "jp r0",
+            "halt r0",
+        ] {
+            let error = first_error(&mut asm, source);
+            assert!(
+                matches!(error, AssemblerError::ArgumentCount(0, ref text) if text == source),
+                "{source}: {error:?}"
+            );
+        }
 
-        let (l, m, r) = split_three_args("a,b, c");
-        assert_eq!(l, "a");
-        assert_eq!(m, "b");
-        assert_eq!(r, "c");
+        let result = asm.assemble("ld\tr1,\t0x02\nhalt ".to_owned()).unwrap();
+        assert_eq!(result.rom(), [0x21, 0x02, 0xC0, 0x00]);
     }
 
     #[test]
@@ -632,6 +1163,606 @@ mod tests {
         assert_eq!(result.rom(), DEMO_ROM);
     }
 
+    #[test]
+    fn ld_move() {
+        let mut asm = Assembler::new();
+
+        // Copy ra into r4, matching the 0x40A4 example from the book
+        let result = asm.assemble("ld r4, ra".to_owned()).unwrap();
+        assert_eq!(result.rom(), [0x40, 0xA4]);
+    }
+
+    #[test]
+    fn labels() {
+        let mut asm = Assembler::new();
+
+        let source = "ld r0, 0x00
+start:
+    ld r1, 0x01
+    jp r1, done     ; Forward
+    jp r0, start    ; Backward
+    jp r0, done
+done:
+    jp r0, 0x0A
+    halt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(
+            result.rom(),
+            [
+                0x20, 0x00, 0x21, 0x01, 0xB1, 0x0A, 0xB0, 0x02, 0xB0, 0x0A, 0xB0, 0x0A, 0xC0, 0x00
+            ]
+        );
+
+        assert_eq!(asm.symbols().len(), 2);
+        assert_eq!(asm.symbols().value("start"), Some(0x02));
+        assert_eq!(asm.symbols().get("done").unwrap().line, 7);
+    }
+
+    #[test]
+    fn label_errors() {
+        let mut asm = Assembler::new();
+
+        let error = first_error(&mut asm, "jp r0, nowhere");
+        assert!(matches!(error, AssemblerError::UndefinedSymbol(0, ref l) if l == "nowhere"));
+
+        let error = first_error(&mut asm, "loop:\nhalt\nloop:\njp r0, loop");
+        assert!(matches!(error, AssemblerError::DuplicateSymbol(2, ref l) if l == "loop"));
+
+        let error = first_error(&mut asm, "1st:\nhalt");
+        assert!(matches!(error, AssemblerError::InvalidName(0, _)));
+    }
+
+    #[test]
+    fn same_line_labels() {
+        let mut asm = Assembler::new();
+
+        let source = "start: ld r0, 0x00
+skip:\tjp r0, end  ; Comment
+end:    halt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(result.rom(), [0x20, 0x00, 0xB0, 0x04, 0xC0, 0x00]);
+        assert_eq!(asm.symbols().value("skip"), Some(0x02));
+    }
+
+    #[test]
+    fn local_labels() {
+        let mut asm = Assembler::new();
+
+        let source = "first:
+.loop:  jp r1, .done
+        jp r0, .loop
+.done:  halt
+second:
+.loop:  jp r0, .loop
+        jp r0, first.done";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(
+            result.rom(),
+            [0xB1, 0x04, 0xB0, 0x00, 0xC0, 0x00, 0xB0, 0x06, 0xB0, 0x04]
+        );
+        assert_eq!(asm.symbols().value("second.loop"), Some(0x06));
+
+        let error = first_error(&mut asm, "first:\n.done: halt\nsecond: jp r0, .done");
+        assert!(matches!(error, AssemblerError::UndefinedSymbol(2, ref l) if l == ".done"));
+    }
+
+    #[test]
+    fn data_directives() {
+        let mut asm = Assembler::new();
+
+        let source = r#"    halt
+table:  .db 0x01, 0b10, table
+        .byte 0xFF
+        .fill 0x03, 0xAA
+        .space 0x03
+        .ascii "Hi; \"there\"\n"   ; Comment
+        halt"#;
+        let result = asm.assemble(source.to_owned()).unwrap();
+
+        let mut rom = vec![
+            0xC0, 0x00, 0x01, 0x02, 0x02, 0xFF, 0xAA, 0xAA, 0xAA, 0x00, 0x00, 0x00,
+        ];
+        rom.extend_from_slice(b"Hi; \"there\"\n");
+        rom.extend_from_slice(&[0xC0, 0x00]);
+        assert_eq!(result.rom(), rom);
+
+        assert_eq!(result.data().len(), 1);
+        assert_eq!(result.data()[0], 2..rom.len() - 2);
+        assert!(!result.is_data(1));
+        assert!(result.is_data(2));
+        assert!(!result.is_data(rom.len() - 2));
+    }
+
+    #[test]
+    fn fill_forward_reference() {
+        let mut asm = Assembler::new();
+
+        let source = "        .fill 4, end\nend:    halt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(result.rom(), [0x04, 0x04, 0x04, 0x04, 0xC0, 0x00]);
+    }
+
+    #[test]
+    fn malformed_strings() {
+        let mut asm = Assembler::new();
+
+        for source in [
+            ".ascii Hi",
+            ".ascii \"Hi",
+            ".ascii \"a\"b\"",
+            ".ascii \"\\q\"",
+            ".ascii \"\u{e9}\"",
+        ] {
+            let error = first_error(&mut asm, source);
+            assert!(
+                matches!(error, AssemblerError::MalformedString(0, _)),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn constants_and_aliases() {
+        let mut asm = Assembler::new();
+
+        let source = ".equ START 0x04
+.equ INPUT, 0x44
+.equ OUTPUT 0x46
+.reg count r5
+.reg total, count
+.org START
+        ld count, 0xFF
+        ld r4, (INPUT)
+        ld total, INPUT
+        ld (OUTPUT), total
+        rot count, START
+        adds total, count, r1
+        jp count, done
+        .db OUTPUT, done
+done:   halt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(
+            result.rom()[4..],
+            [
+                0x25, 0xFF, 0x14, 0x44, 0x25, 0x44, 0x35, 0x46, 0xA5, 0x04, 0x55, 0x51, 0xB5, 0x14,
+                0x46, 0x14, 0xC0, 0x00
+            ]
+        );
+        assert_eq!(result.program_counter(), 0x04);
+
+        let symbol = asm.symbols().get("total").unwrap();
+        assert_eq!(symbol.kind, SymbolKind::Register);
+        assert_eq!(symbol.value, 0x5);
+        assert_eq!(
+            asm.symbols().get("INPUT").unwrap().kind,
+            SymbolKind::Constant
+        );
+    }
+
+    #[test]
+    fn constant_errors() {
+        let mut asm = Assembler::new();
+
+        // Symbols which change the layout must be defined first
+        let error = first_error(&mut asm, ".org START\n.equ START 0x04");
+        assert!(matches!(error, AssemblerError::UndefinedSymbol(0, _)));
+
+        let error = first_error(&mut asm, ".equ ra 0x01");
+        assert!(matches!(error, AssemblerError::InvalidName(0, _)));
+
+        let error = first_error(&mut asm, "done: halt\n.equ done 0x01");
+        assert!(matches!(error, AssemblerError::DuplicateSymbol(1, _)));
+
+        let error = first_error(&mut asm, ".reg acc 0x01");
+        assert!(matches!(error, AssemblerError::TypeMismatch(0, _)));
+
+        let error = first_error(&mut asm, ".reg acc r1\nld (acc), r2");
+        assert!(matches!(error, AssemblerError::MalformedAddress(1, _)));
+    }
+
+    #[test]
+    fn expressions() {
+        let mut asm = Assembler::new();
+
+        let source = ".equ WORD 0x1234
+.equ SIZE 2 * 2
+        ld r1, (table + 3)
+        ld r2, SIZE << 1 | 1
+        ld r3, -2
+        ld (table + SIZE - 1), r3
+        jp r0, $ + 4
+        rot r4, SIZE - 1
+        ld r5, high(WORD)
+table:  .db low(WORD), (1 + 2) * 3, table - $, end - table
+end:    halt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(
+            result.rom(),
+            [
+                0x11, 0x11, 0x22, 0x09, 0x23, 0xFE, 0x33, 0x11, 0xB0, 0x0C, 0xA4, 0x03, 0x25, 0x12,
+                0x34, 0x09, 0x00, 0x04, 0xC0, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn range_errors() {
+        let mut asm = Assembler::new();
+
+        for source in [
+            "ld r0, 256",
+            "ld r0, -129",
+            "ld r0, (0x100)",
+            "ld (-1), r0",
+            "jp r0, -1",
+            "rot r1, 16",
+            ".org 0x100",
+            ".db 0x1FF",
+            ".fill 300",
+            "halt\nend: jp r0, end + 0xFF",
+        ] {
+            let error = first_error(&mut asm, source);
+            assert!(
+                matches!(error, AssemblerError::ValueOutOfRange(..)),
+                "{source}: {error:?}"
+            );
+        }
+
+        let error = first_error(&mut asm, "ld r0, 1 / (2 - 2)");
+        assert!(matches!(error, AssemblerError::DivisionByZero(0, _)));
+
+        let error = first_error(&mut asm, ".org 0xFE\nhalt\nend: jp r0, end");
+        assert!(matches!(error, AssemblerError::ProgramTooLarge(2, _)));
+
+        let error = first_error(&mut asm, "ld r0, r1 + 1");
+        assert!(matches!(error, AssemblerError::TypeMismatch(0, ref name) if name == "r1"));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut asm = Assembler::new();
+
+        // Every bad line is reported, the lines after it keep their addresses
+        let source = "start:  ld r0, 0x01
+        ld rz, 0x01
+        hlt
+start:  jp r0, end  ; Comment
+        .db 0x100
+end:    halt";
+        let diagnostics = asm.assemble(source.to_owned()).unwrap_err();
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.severity(), d.line(), &source[d.span()]))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Severity::Error, 2, "rz"),
+                (Severity::Error, 3, "hlt"),
+                (Severity::Error, 4, "start"),
+                (Severity::Note, 1, "start:  ld r0, 0x01"),
+                (Severity::Error, 5, "0x100"),
+                (Severity::Warning, 6, "end:    halt"),
+            ]
+        );
+        assert_eq!(diagnostics.errors().count(), 4);
+        assert_eq!(asm.symbols().value("end"), Some(0x07));
+        assert_eq!(diagnostics.iter().next().unwrap().column(source), 12);
+
+        // Warnings don't stop the program
+        let source = ".db 0x01\nhalt\n.org 0x00\nhalt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        let found: Vec<_> = result
+            .diagnostics()
+            .iter()
+            .map(|d| (d.severity(), d.line()))
+            .collect();
+        assert_eq!(found, [(Severity::Warning, 2), (Severity::Warning, 3)]);
+        assert_eq!(result.rom(), [0xC0, 0x00]);
+        assert_eq!(
+            result.diagnostics().iter().next().unwrap().message(),
+            "Instruction at 0x01 isn't aligned with the code before it, \
+             the data in between has an odd number of bytes"
+        );
+
+        // Code can be placed on an odd address on purpose
+        let source = ".org 0x01\nld r1, 0x05\n.db 0x01\nhalt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        let found: Vec<_> = result.diagnostics().iter().map(|d| d.line()).collect();
+        assert_eq!(found, [4]);
+    }
+
+    #[test]
+    fn source_map() {
+        let mut asm = Assembler::new();
+
+        let source = ".org 0x02
+start:  ld r0, 0x01   ; Comment
+        .db 0x01, 0x02
+  halt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        let map = result.source_map();
+
+        let span = map.span(0x03).unwrap();
+        assert_eq!((span.line(), span.column()), (2, 9));
+        assert_eq!(&source[span.span()], "ld r0, 0x01");
+        assert_eq!(&source[map.span(0x05).unwrap().span()], ".db 0x01, 0x02");
+        assert_eq!(map.span(0x06).unwrap().column(), 3);
+        assert_eq!(map.span(0x00), None);
+        assert_eq!(map.addresses(3), Some(0x04..0x06));
+        assert_eq!(map.addresses(1), None);
+        assert_eq!(result.symbols().value("start"), Some(0x02));
+
+        // Symbols count lines the same way as the source map
+        let start = result.symbols().get("start").unwrap();
+        assert_eq!(start.line, span.line());
+        assert_eq!(result.symbols().references("start").count(), 0);
+        let result = asm.assemble(format!("{source}\n  jp r0, start")).unwrap();
+        let jump = result.source_map().span(0x08).unwrap();
+        assert_eq!(
+            result.symbols().references("start").collect::<Vec<_>>(),
+            [jump.line()]
+        );
+
+        // Bytes which `.org` moves back over belong to the statements after it
+        let source = ".db 1, 2, 3, 4\n.org 0x02\nhalt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(result.source_map().span(0x01).unwrap().line(), 1);
+        assert_eq!(result.source_map().span(0x02).unwrap().line(), 3);
+        assert_eq!(result.source_map().len(), 2);
+    }
+
+    #[test]
+    fn macros() {
+        let mut asm = Assembler::new();
+
+        let source = "\
+.macro clear reg
+        xor reg, reg, reg
+.endm
+.macro countdown reg, step
+        ld r0, 0x00
+.loop:  adds reg, reg, step
+        jp reg, .done
+        jp r0, .loop
+.done:
+.endm
+        ld r1, 0x03
+        ld r2, 0xFF
+start:  countdown r1, r2   ; Twice
+        countdown r1, r2
+        clear r1
+        halt";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(
+            result.rom(),
+            [
+                0x21, 0x03, 0x22, 0xFF, // Setup
+                0x20, 0x00, 0x51, 0x12, 0xB1, 0x0C, 0xB0, 0x06, // First countdown
+                0x20, 0x00, 0x51, 0x12, 0xB1, 0x14, 0xB0, 0x0E, // Second countdown
+                0x91, 0x11, 0xC0, 0x00,
+            ]
+        );
+
+        // Each expansion has its own local labels
+        let symbols = result.symbols();
+        assert_eq!(symbols.value("start"), Some(0x04));
+        assert_eq!(symbols.value("start.loop__1"), Some(0x06));
+        assert_eq!(symbols.value("start.loop__2"), Some(0x0E));
+        assert_eq!(symbols.get("start.done__2").unwrap().line, 14);
+
+        // The bytes of an expansion belong to the call
+        let map = result.source_map();
+        assert_eq!(&source[map.span(0x08).unwrap().span()], "countdown r1, r2");
+        assert_eq!(map.addresses(13), Some(0x04..0x0C));
+        assert_eq!(map.addresses(14), Some(0x0C..0x14));
+        assert_eq!(map.addresses(6), None);
+    }
+
+    #[test]
+    fn macro_diagnostics() {
+        let mut asm = Assembler::new();
+        let found = |source: &str, diagnostics: &Diagnostics| -> Vec<(Severity, usize, String)> {
+            diagnostics
+                .iter()
+                .map(|d| (d.severity(), d.line(), source[d.span()].to_string()))
+                .collect()
+        };
+
+        // Errors are on the call, with a note on the macro line
+        let source = "\
+.macro store reg, address
+        ld (address), reg
+.endm
+        store r1, 0x10
+        store rz, 0x10";
+        let diagnostics = asm.assemble(source.to_owned()).unwrap_err();
+        assert_eq!(
+            found(source, &diagnostics),
+            [
+                (Severity::Error, 5, "rz".to_string()),
+                (Severity::Note, 2, "ld (address), reg".to_string()),
+            ]
+        );
+        let messages: Vec<_> = diagnostics.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "error: Unknown register 'rz'",
+                "note: In macro 'store' called on line 5"
+            ]
+        );
+
+        // Nested macros note every level, innermost first
+        let source = "\
+.macro inner value
+        ld r1, value
+.endm
+.macro outer value
+        inner value + 1
+.endm
+        outer 0xFF";
+        let diagnostics = asm.assemble(source.to_owned()).unwrap_err();
+        assert_eq!(
+            found(source, &diagnostics),
+            [
+                (Severity::Error, 7, "outer 0xFF".to_string()),
+                (Severity::Note, 2, "ld r1, value".to_string()),
+                (Severity::Note, 5, "inner value + 1".to_string()),
+            ]
+        );
+        assert_eq!(
+            diagnostics.iter().nth(1).unwrap().message(),
+            "In macro 'inner' called on line 5"
+        );
+
+        // Runaway recursion stops at the depth limit
+        let source = ".macro forever\n        forever\n.endm\n        forever";
+        let diagnostics = asm.assemble(source.to_owned()).unwrap_err();
+        assert_eq!(
+            found(source, &diagnostics),
+            [
+                (Severity::Error, 4, "forever".to_string()),
+                (Severity::Note, 2, "forever".to_string()),
+            ]
+        );
+        assert!(matches!(
+            diagnostics.errors().next(),
+            Some(AssemblerError::MacroTooDeep(3, _))
+        ));
+
+        // Warnings inside an expansion point at the call too
+        let source = ".macro pad\n        .db 0x00\n        halt\n.endm\n        pad";
+        let result = asm.assemble(source.to_owned()).unwrap();
+        assert_eq!(
+            found(source, result.diagnostics()),
+            [
+                (Severity::Warning, 5, "pad".to_string()),
+                (Severity::Note, 3, "halt".to_string()),
+            ]
+        );
+
+        // A second definition notes the first
+        let source = ".macro m\n.endm\n.macro m\n.endm";
+        let diagnostics = asm.assemble(source.to_owned()).unwrap_err();
+        assert_eq!(
+            found(source, &diagnostics),
+            [
+                (Severity::Error, 3, "m".to_string()),
+                (Severity::Note, 1, ".macro m".to_string()),
+            ]
+        );
+
+        // Global labels in a macro are reported on each call, local labels can't look generated
+        let source = "\
+.macro wait
+loop:   jp r0, loop
+.endm
+        wait
+        wait
+.done__1:";
+        let diagnostics = asm.assemble(source.to_owned()).unwrap_err();
+        assert_eq!(
+            found(source, &diagnostics),
+            [
+                (Severity::Error, 4, "wait".to_string()),
+                (Severity::Note, 2, "loop".to_string()),
+                (Severity::Error, 5, "wait".to_string()),
+                (Severity::Note, 2, "loop".to_string()),
+                (Severity::Error, 6, ".done__1".to_string()),
+            ]
+        );
+        assert_eq!(
+            diagnostics.iter().next().unwrap().to_string(),
+            "error: Label 'loop' in a macro must be local"
+        );
+    }
+
+    // Pieces of statements, joined at random they reach most of the assembler
+    const WORDS: [&str; 51] = [
+        "ld",
+        "adds",
+        "addf",
+        "or",
+        "and",
+        "xor",
+        "rot",
+        "halt",
+        "jp",
+        "hlt",
+        ".org",
+        ".db",
+        ".fill",
+        ".space",
+        ".equ",
+        ".reg",
+        ".ascii",
+        "start:",
+        ".loop:",
+        "start",
+        ".loop",
+        "r0",
+        "rf",
+        "rz",
+        ",",
+        ", ",
+        "(",
+        ")",
+        "0x",
+        "0xFF",
+        "0x1FF",
+        "0b101",
+        "255",
+        "-128",
+        "-",
+        "~",
+        "+",
+        "*",
+        "/ 0",
+        "<<",
+        "$",
+        "low(",
+        "high(",
+        "\"hi\"",
+        "\"",
+        "\\",
+        ";",
+        "\t",
+        ".macro m a",
+        ".endm",
+        "m ",
+    ];
+
+    fn statement() -> impl Strategy<Value = String> {
+        prop::collection::vec(prop::sample::select(&WORDS[..]), 0..8)
+            .prop_map(|words| words.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn any_text(source in "\\PC*") {
+            let _ = Assembler::new().assemble(source);
+        }
+
+        #[test]
+        fn any_statements(lines in prop::collection::vec(statement(), 0..40)) {
+            let source = lines.join("\n");
+            match Assembler::new().assemble(source) {
+                Ok(result) => {
+                    prop_assert!(result.rom().len() <= MEMORY_SIZE);
+                    prop_assert!(result.rom().len().is_multiple_of(2));
+                }
+                Err(diagnostics) => prop_assert!(diagnostics.has_errors()),
+            }
+        }
+    }
+
+    /// Returns the first error from assembling the source
+    fn first_error(asm: &mut Assembler, source: &str) -> AssemblerError {
+        let diagnostics = asm.assemble(source.to_owned()).unwrap_err();
+        diagnostics.errors().next().unwrap().clone()
+    }
+
     fn decimal_to_register_string(reg: usize) -> Result<String, String> {
         match reg {
             0x0 => Ok("r0".to_owned()),
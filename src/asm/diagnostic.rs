@@ -0,0 +1,185 @@
+//! Errors, warnings and notes found while assembling
+//!
+//! Assembly carries on past a bad line, so one run reports every problem in the source.
+
+use std::{fmt, ops::Range};
+
+use super::AssemblerError;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum Severity {
+    /// Extra information about the diagnostic before it
+    Note,
+
+    /// The program assembles but probably doesn't do what was meant
+    Warning,
+
+    /// The program can't be assembled
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A message about a span of the source
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    severity: Severity,
+    // Source line, starting at 0
+    line: usize,
+    // Byte offsets into the whole source
+    span: Range<usize>,
+    message: String,
+    error: Option<AssemblerError>,
+}
+
+impl Diagnostic {
+    #[must_use]
+    pub fn error(error: AssemblerError, span: Range<usize>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            line: error.line() - 1,
+            span,
+            message: error.to_string(),
+            error: Some(error),
+        }
+    }
+
+    #[must_use]
+    pub fn warning(line: usize, span: Range<usize>, message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            line,
+            span,
+            message,
+            error: None,
+        }
+    }
+
+    #[must_use]
+    pub fn note(line: usize, span: Range<usize>, message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Note,
+            line,
+            span,
+            message,
+            error: None,
+        }
+    }
+
+    #[must_use]
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Returns the source line, starting at 1
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.line + 1
+    }
+
+    /// Returns the byte offsets of the offending text in the source
+    #[must_use]
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns the column the span starts at, counted in characters from 1
+    #[must_use]
+    pub fn column(&self, source: &str) -> usize {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        source[line_start..start].chars().count() + 1
+    }
+
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the error for `Severity::Error` diagnostics
+    #[must_use]
+    pub fn assembler_error(&self) -> Option<&AssemblerError> {
+        self.error.as_ref()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Every diagnostic from a run of the assembler, in source order
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    #[must_use]
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// Returns the errors, without warnings and notes
+    pub fn errors(&self) -> impl Iterator<Item = &AssemblerError> {
+        self.diagnostics
+            .iter()
+            .filter_map(Diagnostic::assembler_error)
+    }
+
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.iter()
+    }
+}
+
+/// One diagnostic per line, as `Line 3: error: Undefined symbol 'end'`
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "Line {}: {diagnostic}", diagnostic.line())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
@@ -0,0 +1,213 @@
+use crate::vole::instruction::Instruction;
+use std::{collections::BTreeSet, ops::Range};
+
+// Column the address comments start at
+const COMMENT_COLUMN: usize = 24;
+
+/// A two byte word from the image
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Word {
+    Code(Instruction),
+    Data(u8, u8),
+}
+
+/// Turns a memory image starting at address 0 back into assembly source.
+///
+/// - Leading zero bytes become an `.org` directive.
+/// - Jump targets get a synthesized `label_xx` label.
+/// - Words which don't decode, or which only decode by ignoring reserved fields,
+///   are written as `.db` directives.
+/// - Trailing zero bytes are dropped.
+#[must_use]
+pub fn disassemble(rom: &[u8]) -> String {
+    disassemble_with_data(rom, &[])
+}
+
+/// Same as `disassemble`, words overlapping the data ranges are always written as `.db` directives.
+///
+/// The ranges are usually `AssemblerResult::data`.
+#[must_use]
+pub fn disassemble_with_data(rom: &[u8], data: &[Range<usize>]) -> String {
+    let words = decode_words(rom, data);
+
+    // Skip the leading padding, it's recreated with .org
+    let start = words
+        .iter()
+        .position(|word| *word != Word::Data(0, 0))
+        .unwrap_or(words.len());
+
+    // Drop the trailing padding
+    let end = words
+        .iter()
+        .rposition(|word| *word != Word::Data(0, 0))
+        .map_or(start, |i| i + 1);
+
+    if start == end {
+        return String::new();
+    }
+
+    let words = &words[start..end];
+    let labels = jump_targets(words, start);
+
+    let mut source = String::new();
+
+    if start > 0 {
+        source.push_str(&format!(".org 0x{:02X}\n\n", start * 2));
+    }
+
+    for (i, word) in words.iter().enumerate() {
+        let address = (start + i) * 2;
+
+        if labels.contains(&address) {
+            source.push_str(&format!("\n{}:\n", label_name(address)));
+        }
+
+        let line = match word {
+            Word::Code(Instruction::Jump { r, address: target })
+                if labels.contains(&(*target as usize)) =>
+            {
+                format!("    jp r{r:x}, {}", label_name(*target as usize))
+            }
+            Word::Code(instruction) => format!("    {instruction}"),
+            Word::Data(high, low) => format!("    .db 0x{high:02X}, 0x{low:02X}"),
+        };
+
+        source.push_str(&format!(
+            "{line:<width$}; 0x{address:02X}\n",
+            width = COMMENT_COLUMN
+        ));
+    }
+
+    source
+}
+
+/// Splits the image into words, an odd trailing byte is treated as data
+fn decode_words(rom: &[u8], data: &[Range<usize>]) -> Vec<Word> {
+    rom.chunks(2)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let high = chunk[0];
+            let low = chunk.get(1).copied().unwrap_or(0);
+            let ir = u16::from_be_bytes([high, low]);
+
+            let address = i * 2;
+            let is_data = data
+                .iter()
+                .any(|range| range.start < address + 2 && address < range.end);
+
+            match Instruction::decode(ir) {
+                Some(instruction) if !is_data && instruction.encode() == ir => {
+                    Word::Code(instruction)
+                }
+                _ => Word::Data(high, low),
+            }
+        })
+        .collect()
+}
+
+/// Returns the addresses of jump targets which land on an instruction in the image
+fn jump_targets(words: &[Word], start: usize) -> BTreeSet<usize> {
+    let is_code = |address: usize| {
+        address.is_multiple_of(2)
+            && address / 2 >= start
+            && matches!(words.get(address / 2 - start), Some(Word::Code(_)))
+    };
+
+    words
+        .iter()
+        .filter_map(|word| match word {
+            Word::Code(Instruction::Jump { address, .. }) => Some(*address as usize),
+            _ => None,
+        })
+        .filter(|address| is_code(*address))
+        .collect()
+}
+
+fn label_name(address: usize) -> String {
+    format!("label_{address:02x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::{DEMO_ROM, assembler::Assembler};
+
+    #[test]
+    fn demo_round_trip() {
+        let source = disassemble(DEMO_ROM);
+        assert!(source.starts_with(".org 0x02\n"));
+        assert!(source.contains("label_0c:\n"));
+        assert!(source.contains("jp r4, label_0c"));
+
+        let result = Assembler::new().assemble(source).unwrap();
+        assert_eq!(result.rom(), DEMO_ROM);
+        assert_eq!(result.program_counter(), 0x02);
+    }
+
+    #[test]
+    fn data_words() {
+        // Invalid op-code, halt with a non-zero operand, a non-zero MOVE reserved field
+        let source = disassemble(&[0xD0, 0x12, 0xC0, 0x01, 0x41, 0x23, 0xC0, 0x00]);
+        let lines: Vec<&str> = source.lines().collect();
+
+        assert_eq!(lines[0], "    .db 0xD0, 0x12      ; 0x00");
+        assert_eq!(lines[1], "    .db 0xC0, 0x01      ; 0x02");
+        assert_eq!(lines[2], "    .db 0x41, 0x23      ; 0x04");
+        assert_eq!(lines[3], "    halt                ; 0x06");
+
+        let result = Assembler::new().assemble(source).unwrap();
+        assert_eq!(
+            result.rom(),
+            [0xD0, 0x12, 0xC0, 0x01, 0x41, 0x23, 0xC0, 0x00]
+        );
+    }
+
+    #[test]
+    fn marked_data() {
+        let result = Assembler::new()
+            .assemble("jp r0, end\ntable: .db 0x21, 0x41\nend: halt".to_owned())
+            .unwrap();
+        assert_eq!(result.data().len(), 1);
+        assert_eq!(result.data()[0], 2..4);
+
+        // The table decodes as an instruction unless it's marked
+        let source = disassemble_with_data(result.rom(), result.data());
+        assert!(source.contains(".db 0x21, 0x41"));
+        assert!(source.contains("jp r0, label_04"));
+        assert!(disassemble(result.rom()).contains("ld r1, 0x41"));
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        assert_eq!(disassemble(&[]), "");
+        assert_eq!(disassemble(&[0x00; 256]), "");
+
+        // The odd byte is padded with zero
+        let source = disassemble(&[0xC0, 0x00, 0xFF]);
+        assert!(source.contains(".db 0xFF, 0x00"));
+    }
+
+    #[test]
+    fn backward_jump_label() {
+        // Load 0x01 into r1, jump back to it forever
+        let source = disassemble(&[0x21, 0x01, 0xB0, 0x00]);
+        let lines: Vec<&str> = source.lines().map(str::trim).collect();
+
+        assert_eq!(lines[1], "label_00:");
+        assert!(lines[3].starts_with("jp r0, label_00"));
+
+        let result = Assembler::new().assemble(source).unwrap();
+        assert_eq!(result.rom(), [0x21, 0x01, 0xB0, 0x00]);
+    }
+
+    #[test]
+    fn jump_outside_code() {
+        // The target isn't an instruction so it's written as an address
+        let source = disassemble(&[0xB0, 0x05, 0xC0, 0x00]);
+        assert!(source.contains("jp r0, 0x05"));
+        assert!(!source.contains("label_"));
+
+        let result = Assembler::new().assemble(source).unwrap();
+        assert_eq!(result.rom(), [0xB0, 0x05, 0xC0, 0x00]);
+    }
+}
@@ -0,0 +1,369 @@
+//! Constant expressions in operands
+//!
+//! From lowest to highest precedence: `|`, `^`, `&`, `<<` `>>`, `+` `-`, `*` `/`,
+//! then unary `-` `~`. Values are numbers (`0x` hex, `0b` binary or decimal), names,
+//! `$` for the current address, `low(x)` and `high(x)` for the bytes of a 16-bit value,
+//! and parenthesized expressions.
+
+use super::AssemblerError;
+use crate::rom;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: [&str; 10] = ["<<", ">>", "+", "-", "*", "/", "&", "|", "^", "~"];
+
+/// Binary operators by precedence, lowest first
+const PRECEDENCE: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/"],
+];
+
+/// Parentheses and unary operators recurse, deeper nesting than this is an error rather than a
+/// stack overflow
+const MAX_DEPTH: usize = 64;
+
+/// Evaluates the expression, `resolve` returns the value of a name or `$`
+///
+/// # Errors
+///
+/// Returns `MalformedExpression` or `MalformedNumber` if the text doesn't parse,
+/// `DivisionByZero`, `ValueOutOfRange` if the arithmetic overflows, `NestingTooDeep`, and any
+/// error from `resolve`.
+pub fn evaluate(
+    text: &str,
+    line: usize,
+    resolve: &dyn Fn(&str) -> Result<i64, AssemblerError>,
+) -> Result<i64, AssemblerError> {
+    let tokens = tokenize(text, line)?;
+    let mut parser = Parser {
+        text,
+        line,
+        tokens: &tokens,
+        position: 0,
+        depth: 0,
+        resolve,
+    };
+
+    let value = parser.binary(0)?;
+    if parser.position != tokens.len() {
+        return Err(parser.malformed());
+    }
+
+    Ok(value)
+}
+
+fn tokenize(text: &str, line: usize) -> Result<Vec<Token>, AssemblerError> {
+    let malformed = || AssemblerError::MalformedExpression(line, text.to_string());
+
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let length = if c.is_ascii_digit() {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let number = &rest[..length];
+            let value = rom::parse_number(number)
+                .and_then(|value| i64::try_from(value).ok())
+                .ok_or_else(|| AssemblerError::MalformedNumber(line, number.to_string()))?;
+            tokens.push(Token::Number(value));
+            length
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '$' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..length].to_string()));
+            length
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            1
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            1
+        } else {
+            let operator = OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(**operator))
+                .ok_or_else(malformed)?;
+            tokens.push(Token::Operator(operator));
+            operator.len()
+        };
+
+        rest = rest[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    line: usize,
+    tokens: &'a [Token],
+    position: usize,
+    depth: usize,
+    resolve: &'a dyn Fn(&str) -> Result<i64, AssemblerError>,
+}
+
+impl Parser<'_> {
+    fn malformed(&self) -> AssemblerError {
+        AssemblerError::MalformedExpression(self.line, self.text.to_string())
+    }
+
+    fn overflow(&self) -> AssemblerError {
+        AssemblerError::ValueOutOfRange(self.line, self.text.to_string())
+    }
+
+    /// Goes one level deeper, call `leave` afterwards
+    fn enter(&mut self) -> Result<(), AssemblerError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(AssemblerError::NestingTooDeep(
+                self.line,
+                self.text.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn peek_operator(&self, operators: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) if operators.contains(operator) => Some(*operator),
+            _ => None,
+        }
+    }
+
+    /// Binary operators at the precedence level and above
+    fn binary(&mut self, level: usize) -> Result<i64, AssemblerError> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+
+        let mut value = self.binary(level + 1)?;
+        while let Some(operator) = self.peek_operator(operators) {
+            self.position += 1;
+            let rhs = self.binary(level + 1)?;
+
+            value = match operator {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => u32::try_from(rhs)
+                    .ok()
+                    .and_then(|rhs| value.checked_shl(rhs))
+                    .ok_or_else(|| self.overflow())?,
+                ">>" => u32::try_from(rhs)
+                    .ok()
+                    .and_then(|rhs| value.checked_shr(rhs))
+                    .ok_or_else(|| self.overflow())?,
+                "+" => value.checked_add(rhs).ok_or_else(|| self.overflow())?,
+                "-" => value.checked_sub(rhs).ok_or_else(|| self.overflow())?,
+                "*" => value.checked_mul(rhs).ok_or_else(|| self.overflow())?,
+                _ => {
+                    if rhs == 0 {
+                        return Err(AssemblerError::DivisionByZero(
+                            self.line,
+                            self.text.to_string(),
+                        ));
+                    }
+                    value.checked_div(rhs).ok_or_else(|| self.overflow())?
+                }
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, AssemblerError> {
+        let Some(operator) = self.peek_operator(&["-", "~"]) else {
+            return self.primary();
+        };
+
+        self.position += 1;
+        self.enter()?;
+        let value = self.unary()?;
+        self.leave();
+
+        match operator {
+            "-" => value.checked_neg().ok_or_else(|| self.overflow()),
+            _ => Ok(!value),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, AssemblerError> {
+        match self.next().cloned() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Open) => self.parenthesized(),
+            Some(Token::Name(name)) => {
+                let function = name.to_lowercase();
+                if matches!(function.as_str(), "low" | "high")
+                    && self.tokens.get(self.position) == Some(&Token::Open)
+                {
+                    self.position += 1;
+                    let value = self.parenthesized()?;
+                    return Ok(match function.as_str() {
+                        "low" => value & 0xFF,
+                        _ => (value >> 8) & 0xFF,
+                    });
+                }
+
+                (self.resolve)(&name)
+            }
+            _ => Err(self.malformed()),
+        }
+    }
+
+    /// The rest of an expression after an opening parenthesis
+    fn parenthesized(&mut self) -> Result<i64, AssemblerError> {
+        self.enter()?;
+        let value = self.binary(0)?;
+        self.leave();
+
+        match self.next() {
+            Some(Token::Close) => Ok(value),
+            _ => Err(self.malformed()),
+        }
+    }
+}
+
+/// Returns the inside of the parentheses if they enclose all of the text, as in `(table+3)`
+/// but not `(1+2)*(3+4)`
+pub fn enclosed(text: &str) -> Option<&str> {
+    let inner = text.strip_prefix('(')?.strip_suffix(')')?;
+
+    let mut depth = 0;
+    for c in inner.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return None,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    Some(inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(name: &str) -> Result<i64, AssemblerError> {
+        match name {
+            "$" => Ok(0x10),
+            "table" => Ok(0x40),
+            ".loop" => Ok(0x08),
+            _ => Err(AssemblerError::UndefinedSymbol(0, name.to_string())),
+        }
+    }
+
+    fn eval(text: &str) -> Result<i64, AssemblerError> {
+        evaluate(text, 0, &resolve)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(eval("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(eval("10 - 4 - 3").unwrap(), 3);
+        assert_eq!(eval("0x0F & 0b1010 | 0x40").unwrap(), 0x4A);
+        assert_eq!(eval("1 << 4 + 1").unwrap(), 0x20);
+        assert_eq!(eval("0xF0 >> 4 ^ 0x01").unwrap(), 0x0E);
+        assert_eq!(eval("-1").unwrap(), -1);
+        assert_eq!(eval("~0 & 0xFF").unwrap(), 0xFF);
+        assert_eq!(eval("7 / 2").unwrap(), 3);
+    }
+
+    #[test]
+    fn names_and_helpers() {
+        assert_eq!(eval("table+3").unwrap(), 0x43);
+        assert_eq!(eval("$ + 2").unwrap(), 0x12);
+        assert_eq!(eval(".loop - $").unwrap(), -8);
+        assert_eq!(eval("low(0x1234)").unwrap(), 0x34);
+        assert_eq!(eval("HIGH(0x1234) + 1").unwrap(), 0x13);
+        assert!(matches!(
+            eval("missing + 1"),
+            Err(AssemblerError::UndefinedSymbol(_, ref name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        for text in ["", "1 +", "(1", "1)", "1 2", "* 2", "1 @ 2", "low(1"] {
+            assert!(
+                matches!(eval(text), Err(AssemblerError::MalformedExpression(..))),
+                "{text}"
+            );
+        }
+
+        assert!(matches!(
+            eval("0xZZ"),
+            Err(AssemblerError::MalformedNumber(..))
+        ));
+        assert!(matches!(
+            eval("0b102"),
+            Err(AssemblerError::MalformedNumber(..))
+        ));
+        assert!(matches!(
+            eval("1 / 0"),
+            Err(AssemblerError::DivisionByZero(..))
+        ));
+        assert!(matches!(
+            eval("1 << 64"),
+            Err(AssemblerError::ValueOutOfRange(..))
+        ));
+        assert!(matches!(
+            eval("0x7FFFFFFFFFFFFFFF + 1"),
+            Err(AssemblerError::ValueOutOfRange(..))
+        ));
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(eval(&nested(MAX_DEPTH)).unwrap(), 1);
+        assert!(matches!(
+            eval(&nested(MAX_DEPTH + 1)),
+            Err(AssemblerError::NestingTooDeep(..))
+        ));
+
+        assert_eq!(eval(&format!("{}1", "-".repeat(MAX_DEPTH))).unwrap(), 1);
+        assert!(matches!(
+            eval(&format!("{}1", "~".repeat(100_000))),
+            Err(AssemblerError::NestingTooDeep(..))
+        ));
+        assert!(matches!(
+            eval(&"(".repeat(100_000)),
+            Err(AssemblerError::NestingTooDeep(..))
+        ));
+    }
+
+    #[test]
+    fn enclosing_parentheses() {
+        assert_eq!(enclosed("(table+3)"), Some("table+3"));
+        assert_eq!(enclosed("((1))"), Some("(1)"));
+        assert_eq!(enclosed("(1+2)*(3+4)"), None);
+        assert_eq!(enclosed("1+2"), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! Assembler listings: each source line next to its address and bytes, then the symbol table
+//! and a cross-reference of where each symbol is used
+
+use std::fmt;
+
+use super::{
+    asm_result::AssemblerResult,
+    symbols::{SymbolKind, SymbolTable},
+};
+
+/// Bytes shown on a row, longer statements carry on over the following rows
+const BYTES_PER_ROW: usize = 4;
+
+/// A row of the listing
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ListingLine {
+    /// Address of the first byte, `None` if the row has no bytes
+    pub address: Option<u8>,
+
+    pub bytes: Vec<u8>,
+
+    /// Source line starting at 1, `None` on rows continuing the bytes of the line above
+    pub line: Option<usize>,
+
+    /// The source line as written, comments included
+    pub source: String,
+}
+
+/// A program listing, displays as the text handed in alongside a program
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Listing {
+    lines: Vec<ListingLine>,
+    symbols: SymbolTable,
+}
+
+impl Listing {
+    /// Lists the result of assembling `source`
+    #[must_use]
+    pub fn new(source: &str, result: &AssemblerResult) -> Self {
+        let mut lines = Vec::new();
+
+        for (index, text) in source.split_terminator('\n').enumerate() {
+            let line = index + 1;
+            let source = text.trim_end_matches('\r').to_string();
+
+            let mut rows = result
+                .source_map()
+                .iter()
+                .filter(|(_, span)| span.line() == line)
+                .flat_map(|(addresses, _)| {
+                    let bytes = &result.rom()[addresses.clone()];
+                    bytes
+                        .chunks(BYTES_PER_ROW)
+                        .zip(addresses.step_by(BYTES_PER_ROW))
+                        .map(|(bytes, address)| (address, bytes.to_vec()))
+                })
+                .map(|(address, bytes)| ListingLine {
+                    address: u8::try_from(address).ok(),
+                    bytes,
+                    line: None,
+                    source: String::new(),
+                })
+                .collect::<Vec<_>>();
+
+            match rows.first_mut() {
+                Some(first) => {
+                    first.line = Some(line);
+                    first.source = source;
+                }
+                None => rows.push(ListingLine {
+                    address: None,
+                    bytes: Vec::new(),
+                    line: Some(line),
+                    source,
+                }),
+            }
+            lines.extend(rows);
+        }
+
+        Listing {
+            lines,
+            symbols: result.symbols().clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn lines(&self) -> &[ListingLine] {
+        &self.lines
+    }
+
+    /// Returns the symbols of the program with the lines they are used on
+    #[must_use]
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+}
+
+/// Address, bytes, line number and source columns, followed by the symbol table and the
+/// cross-reference
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes_width = BYTES_PER_ROW * 3 - 1;
+        writeln!(f, "Addr  {:<bytes_width$}  Line  Source", "Bytes")?;
+
+        for row in &self.lines {
+            let address = row
+                .address
+                .map(|address| format!("0x{address:02X}"))
+                .unwrap_or_default();
+            let bytes = row
+                .bytes
+                .iter()
+                .map(|byte| format!("{byte:02X}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let line = row.line.map(|line| line.to_string()).unwrap_or_default();
+
+            let text = format!(
+                "{address:<4}  {bytes:<bytes_width$}  {line:>4}  {}",
+                row.source
+            );
+            writeln!(f, "{}", text.trim_end())?;
+        }
+
+        if self.symbols.is_empty() {
+            return Ok(());
+        }
+
+        let name_width = self
+            .symbols
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Name".len());
+
+        writeln!(f)?;
+        writeln!(f, "Symbols:")?;
+        writeln!(f, "{:<name_width$}  Kind      Value  Line", "Name")?;
+        for (name, symbol) in self.symbols.iter() {
+            let (kind, value) = match symbol.kind {
+                SymbolKind::Label => ("label", format!("0x{:02X}", symbol.value)),
+                SymbolKind::Constant if symbol.value < 0 => ("constant", symbol.value.to_string()),
+                SymbolKind::Constant => ("constant", format!("0x{:02X}", symbol.value)),
+                SymbolKind::Register => ("register", format!("r{:X}", symbol.value)),
+            };
+            writeln!(
+                f,
+                "{name:<name_width$}  {kind:<8}  {value:>5}  {:>4}",
+                symbol.line
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Cross-reference:")?;
+        writeln!(f, "{:<name_width$}  Defined  Used on", "Name")?;
+        for (name, symbol) in self.symbols.iter() {
+            let used = self
+                .symbols
+                .references(name)
+                .map(|line| line.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let text = format!("{name:<name_width$}  {:>7}  {used}", symbol.line);
+            writeln!(f, "{}", text.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assembler::Assembler;
+
+    fn listing(source: &str) -> Listing {
+        let result = Assembler::new().assemble(source.to_string()).unwrap();
+        Listing::new(source, &result)
+    }
+
+    #[test]
+    fn rows() {
+        let source = "\
+.equ COUNT, 3   ; Times round
+start:  ld r0, COUNT
+        .ascii \"Hello\"
+";
+        let listing = listing(source);
+
+        assert_eq!(
+            listing.lines(),
+            [
+                ListingLine {
+                    address: None,
+                    bytes: vec![],
+                    line: Some(1),
+                    source: ".equ COUNT, 3   ; Times round".to_string(),
+                },
+                ListingLine {
+                    address: Some(0x00),
+                    bytes: vec![0x20, 0x03],
+                    line: Some(2),
+                    source: "start:  ld r0, COUNT".to_string(),
+                },
+                ListingLine {
+                    address: Some(0x02),
+                    bytes: b"Hell".to_vec(),
+                    line: Some(3),
+                    source: "        .ascii \"Hello\"".to_string(),
+                },
+                ListingLine {
+                    address: Some(0x06),
+                    bytes: b"o".to_vec(),
+                    line: None,
+                    source: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn text() {
+        let source = "\
+.reg counter, r1
+.equ STEP, 1
+        ld counter, 0x00   ; Count from 0
+loop:   ld r2, STEP
+        adds counter, counter, r2
+        jp r0, loop
+        halt
+";
+        let expected = "\
+Addr  Bytes        Line  Source
+                      1  .reg counter, r1
+                      2  .equ STEP, 1
+0x00  21 00           3          ld counter, 0x00   ; Count from 0
+0x02  22 01           4  loop:   ld r2, STEP
+0x04  51 12           5          adds counter, counter, r2
+0x06  B0 02           6          jp r0, loop
+0x08  C0 00           7          halt
+
+Symbols:
+Name     Kind      Value  Line
+STEP     constant   0x01     2
+counter  register     r1     1
+loop     label      0x02     4
+
+Cross-reference:
+Name     Defined  Used on
+STEP           2  4
+counter        1  3, 5
+loop           4  6
+";
+        assert_eq!(listing(source).to_string(), expected);
+    }
+}
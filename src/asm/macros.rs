@@ -0,0 +1,552 @@
+//! Macros, `.macro name param, ...` up to `.endm`
+//!
+//! Each call is replaced by the body of its macro before the program is assembled, with every
+//! parameter name replaced by its argument. Local labels defined in the body are renamed on each
+//! expansion, so a macro with a loop can be used more than once under the same label. Their new
+//! names contain `__`, which local labels in the source can't.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use super::{
+    AssemblerError,
+    assembler::{INSTRUCTIONS, is_identifier, strip_comment},
+};
+
+/// Macros calling macros deeper than this are an error, rather than recursing forever
+const MAX_DEPTH: usize = 16;
+
+/// A call expanding to more lines than this is an error, macros calling each other several times
+/// over grow exponentially
+const MAX_LINES: usize = 10_000;
+
+/// A line to assemble, from the source or from the body of a macro
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ExpandedLine {
+    /// The source line, or for a line from a macro its body line without the comment
+    pub text: String,
+
+    /// Source line the text is assembled as, the outermost call for a line from a macro,
+    /// starting at 0
+    pub line: usize,
+
+    /// Byte range of the outermost call within its line, for a line from a macro
+    pub call: Option<Range<usize>>,
+
+    /// Each macro the line was expanded from with the body line it came from, outermost first
+    pub expansion: Vec<(String, usize)>,
+
+    /// A problem with a definition or call, the line is reported instead of assembled
+    pub error: Option<AssemblerError>,
+}
+
+/// The source with every macro call replaced by its body
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Expansion {
+    /// Every line to assemble in order, definitions are left out
+    pub lines: Vec<ExpandedLine>,
+
+    /// The line each macro is defined on, starting at 0
+    pub definitions: BTreeMap<String, usize>,
+}
+
+/// The name and parameters from `.macro name param, ...`
+#[derive(Debug)]
+struct Definition {
+    name: String,
+    params: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    // Source lines between `.macro` and `.endm`
+    body: Range<usize>,
+}
+
+/// Expands every call, macros can be called before or after their definition
+#[must_use]
+pub fn expand(source_lines: &[&str]) -> Expansion {
+    let mut expander = Expander {
+        source_lines,
+        macros: BTreeMap::new(),
+        lines: Vec::new(),
+        count: 0,
+        call_start: None,
+        overflow: false,
+    };
+    let mut definitions = BTreeMap::new();
+
+    // Definitions are found first, lines outside them are kept with any error from a definition
+    let mut kept = Vec::new();
+    let mut open: Option<(usize, Result<Definition, AssemblerError>)> = None;
+    for (line, text) in source_lines.iter().enumerate() {
+        let code = strip_comment(text).trim();
+        let (directive, args) = code.split_once(char::is_whitespace).unwrap_or((code, ""));
+
+        match directive.to_lowercase().as_str() {
+            ".macro" if open.is_none() => open = Some((line, parse_definition(line, code, args))),
+            ".macro" => {
+                kept.push((
+                    line,
+                    Some(AssemblerError::MalformedMacro(line, code.to_string())),
+                ));
+            }
+            ".endm" => {
+                let Some((start, definition)) = open.take() else {
+                    kept.push((
+                        line,
+                        Some(AssemblerError::MalformedMacro(line, code.to_string())),
+                    ));
+                    continue;
+                };
+                let error = definition.and_then(|Definition { name, params }| {
+                    if definitions.contains_key(&name) {
+                        return Err(AssemblerError::DuplicateMacro(start, name));
+                    }
+
+                    definitions.insert(name.clone(), start);
+                    let body = start + 1..line;
+                    expander.macros.insert(name, Macro { params, body });
+                    Ok(())
+                });
+                if let Err(error) = error {
+                    kept.push((start, Some(error)));
+                }
+            }
+            // Renamed labels can't clash with a label from the source
+            _ if local_label(code).is_some_and(|label| label.contains("__")) => {
+                let label = local_label(code).unwrap_or_default().to_string();
+                kept.push((line, Some(AssemblerError::InvalidName(line, label))));
+            }
+            _ if open.is_some() => {}
+            _ => kept.push((line, None)),
+        }
+    }
+
+    if let Some((start, definition)) = open {
+        let name = definition.map_or_else(|e| e.text().to_string(), |definition| definition.name);
+        kept.push((start, Some(AssemblerError::UnterminatedMacro(start, name))));
+    }
+
+    // Errors from definitions are reported in line order with the rest
+    kept.sort_by_key(|(line, _)| *line);
+    for (line, error) in kept {
+        match error {
+            Some(error) => expander.push(ExpandedLine {
+                line,
+                error: Some(error),
+                ..ExpandedLine::default()
+            }),
+            None => expander.line(source_lines[line], line, None, &[]),
+        }
+    }
+
+    Expansion {
+        lines: expander.lines,
+        definitions,
+    }
+}
+
+fn parse_definition(line: usize, code: &str, args: &str) -> Result<Definition, AssemblerError> {
+    let args = args.trim();
+    let (name, params) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+
+    if name.is_empty() {
+        return Err(AssemblerError::MalformedMacro(line, code.to_string()));
+    }
+    if !is_identifier(name) || INSTRUCTIONS.contains(&name.to_lowercase().as_str()) {
+        return Err(AssemblerError::InvalidName(line, name.to_string()));
+    }
+
+    let params =
+        split_args(params).ok_or_else(|| AssemblerError::MalformedMacro(line, code.to_string()))?;
+    for (i, param) in params.iter().enumerate() {
+        if !is_identifier(param) || params[..i].contains(param) {
+            return Err(AssemblerError::InvalidName(line, param.clone()));
+        }
+    }
+
+    Ok(Definition {
+        name: name.to_string(),
+        params,
+    })
+}
+
+/// Splits comma separated arguments, commas inside quotes or parentheses don't count
+///
+/// Returns `None` if an argument is empty.
+fn split_args(args: &str) -> Option<Vec<String>> {
+    let args = args.trim();
+    if args.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let mut split = Vec::new();
+    let mut start = 0;
+    let mut depth = 0_usize;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in args.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth = depth.saturating_sub(1),
+            ',' if !quoted && depth == 0 => {
+                split.push(args[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(args[start..].trim().to_string());
+
+    (!split.iter().any(String::is_empty)).then_some(split)
+}
+
+/// Replaces whole names outside of quotes, names include dots so `.loop` is one name
+fn substitute(code: &str, names: &BTreeMap<String, String>) -> String {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+
+    let mut result = String::with_capacity(code.len());
+    let mut name_start = None;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in code.char_indices() {
+        if !quoted && is_name(c) {
+            name_start.get_or_insert(i);
+            continue;
+        }
+
+        if let Some(start) = name_start.take() {
+            let name = &code[start..i];
+            result.push_str(names.get(name).map_or(name, String::as_str));
+        }
+
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ => {}
+        }
+        result.push(c);
+    }
+
+    if let Some(start) = name_start {
+        let name = &code[start..];
+        result.push_str(names.get(name).map_or(name, String::as_str));
+    }
+
+    result
+}
+
+/// The label a line defines, `name:` or `.name:`
+fn label(code: &str) -> Option<&str> {
+    let first = code.split_whitespace().next()?;
+    let label = first.strip_suffix(':')?;
+    is_identifier(label.strip_prefix('.').unwrap_or(label)).then_some(label)
+}
+
+/// The local label a line defines, `.name:`
+fn local_label(code: &str) -> Option<&str> {
+    label(code).filter(|label| label.starts_with('.'))
+}
+
+struct Expander<'a> {
+    source_lines: &'a [&'a str],
+    macros: BTreeMap<String, Macro>,
+    lines: Vec<ExpandedLine>,
+    // Expansions so far, numbers the local labels of the next one
+    count: usize,
+    // First line of the outermost call being expanded
+    call_start: Option<usize>,
+    // The call has gone past `MAX_LINES`, the rest of it is skipped
+    overflow: bool,
+}
+
+impl Expander<'_> {
+    fn push(&mut self, line: ExpandedLine) {
+        match self.call_start {
+            Some(start) if self.lines.len() - start >= MAX_LINES => self.overflow = true,
+            _ => self.lines.push(line),
+        }
+    }
+
+    /// Adds the line, or the body of the macro it calls
+    fn line(
+        &mut self,
+        text: &str,
+        line: usize,
+        call: Option<Range<usize>>,
+        expansion: &[(String, usize)],
+    ) {
+        if self.overflow {
+            return;
+        }
+
+        let plain = |text: &str| ExpandedLine {
+            text: text.to_string(),
+            line,
+            call: call.clone(),
+            expansion: expansion.to_vec(),
+            error: None,
+        };
+
+        let code = strip_comment(text).trim_end();
+        let trimmed = code.trim_start();
+        let (first, rest) = trimmed
+            .split_once(char::is_whitespace)
+            .unwrap_or((trimmed, ""));
+        let statement = match first.strip_suffix(':') {
+            Some(_) => rest.trim_start(),
+            None => trimmed,
+        };
+        let (name, args) = statement
+            .split_once(char::is_whitespace)
+            .unwrap_or((statement, ""));
+
+        let Some(definition) = self.macros.get(name).cloned() else {
+            self.push(plain(text));
+            return;
+        };
+
+        // The label goes on a line of its own, pointing at the first line of the body
+        let start = code.len() - statement.len();
+        if statement.len() < trimmed.len() {
+            self.push(plain(&code[..start]));
+        }
+
+        let outermost = call.is_none();
+        let call = call.clone().unwrap_or(start..code.len());
+        if outermost {
+            self.call_start = Some(self.lines.len());
+        }
+
+        let error = |error: AssemblerError| ExpandedLine {
+            line,
+            call: Some(call.clone()),
+            expansion: expansion.to_vec(),
+            error: Some(error),
+            ..ExpandedLine::default()
+        };
+
+        if expansion.len() >= MAX_DEPTH {
+            self.push(error(AssemblerError::MacroTooDeep(line, name.to_string())));
+            return;
+        }
+
+        let args = match split_args(args) {
+            Some(args) if args.len() == definition.params.len() => args,
+            _ => {
+                let statement = statement.to_string();
+                self.push(error(AssemblerError::ArgumentCount(line, statement)));
+                return;
+            }
+        };
+
+        self.count += 1;
+        let mut names: BTreeMap<String, String> =
+            definition.params.iter().cloned().zip(args).collect();
+        for body_line in definition.body.clone() {
+            if let Some(label) = local_label(strip_comment(self.source_lines[body_line])) {
+                names.insert(label.to_string(), format!("{label}__{}", self.count));
+            }
+        }
+
+        for body_line in definition.body {
+            let code = strip_comment(self.source_lines[body_line]);
+            let text = substitute(code, &names);
+
+            let mut expansion = expansion.to_vec();
+            expansion.push((name.to_string(), body_line));
+
+            // A global label would be defined again by the next call, and change the scope
+            if let Some(label) = label(&text).filter(|label| !label.starts_with('.')) {
+                self.push(ExpandedLine {
+                    line,
+                    call: Some(call.clone()),
+                    expansion,
+                    error: Some(AssemblerError::MacroLabel(line, label.to_string())),
+                    ..ExpandedLine::default()
+                });
+                continue;
+            }
+
+            self.line(&text, line, Some(call.clone()), &expansion);
+        }
+
+        if outermost && let Some(start) = self.call_start.take() {
+            if self.overflow {
+                self.lines.truncate(start);
+                self.lines
+                    .push(error(AssemblerError::MacroTooLarge(line, name.to_string())));
+            }
+            self.overflow = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_text(source: &str) -> Vec<String> {
+        let lines: Vec<&str> = source.split_terminator('\n').collect();
+        expand(&lines)
+            .lines
+            .into_iter()
+            .map(|line| line.text.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn substitution() {
+        let source = "\
+.macro copy dest, src   ; Two registers
+        ld dest, src
+        .ascii \"dest\"
+.endm
+start:  copy r1, (table + 1)
+";
+        assert_eq!(
+            expand_text(source),
+            ["start:", "ld r1, (table + 1)", ".ascii \"dest\""]
+        );
+    }
+
+    #[test]
+    fn unique_local_labels() {
+        let source = "\
+.macro wait reg
+.loop:  adds reg, reg, r1
+        jp reg, .done
+        jp r0, .loop
+.endm
+        wait r2
+        wait r3
+.done:  halt
+";
+        assert_eq!(
+            expand_text(source),
+            [
+                ".loop__1:  adds r2, r2, r1",
+                "jp r2, .done",
+                "jp r0, .loop__1",
+                ".loop__2:  adds r3, r3, r1",
+                "jp r3, .done",
+                "jp r0, .loop__2",
+                ".done:  halt",
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_calls() {
+        let source = "\
+.macro clear reg
+        xor reg, reg, reg
+.endm
+.macro clear2 a, b
+        clear a
+        clear b
+.endm
+        clear2 r1, r2
+";
+        let lines: Vec<&str> = source.split_terminator('\n').collect();
+        let expansion = expand(&lines);
+
+        assert_eq!(expansion.lines.len(), 2);
+        let line = &expansion.lines[1];
+        assert_eq!(line.text.trim(), "xor r2, r2, r2");
+        assert_eq!(line.line, 7);
+        assert_eq!(line.call, Some(8..21));
+        assert_eq!(
+            line.expansion,
+            [("clear2".to_string(), 5), ("clear".to_string(), 1)]
+        );
+        assert_eq!(expansion.definitions.get("clear2"), Some(&3));
+    }
+
+    #[test]
+    fn definition_errors() {
+        let errors = |source: &str| -> Vec<AssemblerError> {
+            let lines: Vec<&str> = source.split_terminator('\n').collect();
+            expand(&lines)
+                .lines
+                .into_iter()
+                .filter_map(|line| line.error)
+                .collect()
+        };
+
+        assert_eq!(
+            errors(".macro\n.endm\n.endm"),
+            [
+                AssemblerError::MalformedMacro(0, ".macro".to_string()),
+                AssemblerError::MalformedMacro(2, ".endm".to_string()),
+            ]
+        );
+        assert_eq!(
+            errors(".macro ld r\n.endm\n.macro m a, a\n.endm"),
+            [
+                AssemblerError::InvalidName(0, "ld".to_string()),
+                AssemblerError::InvalidName(2, "a".to_string()),
+            ]
+        );
+        assert_eq!(
+            errors(".macro m\n.endm\n.macro m\n.endm\n.macro open\nhalt"),
+            [
+                AssemblerError::DuplicateMacro(2, "m".to_string()),
+                AssemblerError::UnterminatedMacro(4, "open".to_string()),
+            ]
+        );
+        assert_eq!(
+            errors(".macro m\n.x__1:\n.endm\n.y__2: halt\nm"),
+            [
+                AssemblerError::InvalidName(1, ".x__1".to_string()),
+                AssemblerError::InvalidName(3, ".y__2".to_string()),
+            ]
+        );
+        assert_eq!(
+            errors(".macro m a\n.endm\nm\nm 1, 2\nm ,"),
+            [
+                AssemblerError::ArgumentCount(2, "m".to_string()),
+                AssemblerError::ArgumentCount(3, "m 1, 2".to_string()),
+                AssemblerError::ArgumentCount(4, "m ,".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let lines = [".macro forever", "forever", ".endm", "forever"];
+        let expansion = expand(&lines);
+        assert_eq!(expansion.lines.len(), 1);
+        assert_eq!(
+            expansion.lines[0].error,
+            Some(AssemblerError::MacroTooDeep(3, "forever".to_string()))
+        );
+        assert_eq!(expansion.lines[0].expansion.len(), MAX_DEPTH);
+
+        // Each level doubles the lines
+        let mut source = vec![".macro m0", "halt", ".endm"];
+        let definitions: Vec<String> = (1..MAX_DEPTH)
+            .map(|i| format!(".macro m{i}\nm{0}\nm{0}\n.endm", i - 1))
+            .collect();
+        for definition in &definitions {
+            source.extend(definition.lines());
+        }
+        source.extend(["m15", "halt"]);
+
+        let expansion = expand(&source);
+        assert_eq!(expansion.lines.len(), 2);
+        assert_eq!(
+            expansion.lines[0].error,
+            Some(AssemblerError::MacroTooLarge(
+                source.len() - 2,
+                "m15".to_string()
+            ))
+        );
+        assert_eq!(expansion.lines[1].text, "halt");
+    }
+}
@@ -1,30 +1,147 @@
 pub mod asm_result;
 pub mod assembler;
+pub mod diagnostic;
+pub mod disassembler;
+pub mod expression;
+pub mod listing;
+pub mod macros;
+pub mod source_map;
+pub mod symbols;
 
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
 pub enum AssemblerError {
-    #[error("Malformed memory address '{1}' at line {0}")]
+    #[error("Malformed memory address '{1}'")]
     MalformedAddress(usize, String),
 
-    #[error("Unknown register '{1}' at line {0}")]
+    #[error("Unknown register '{1}'")]
     UnknownRegister(usize, String),
 
-    #[error("Malformed number '{1}' at line {0}")]
+    #[error("Malformed number '{1}'")]
     MalformedNumber(usize, String),
 
-    #[error("Type mismatch '{1}' at line {0}")]
+    #[error("Type mismatch '{1}'")]
     TypeMismatch(usize, String),
 
-    #[error("{1} at line {0}")]
+    #[error("{1}")]
     LoadOpFail(usize, String),
 
-    #[error("Error resolving label '{1}' at line {0}")]
-    LabelResolution(usize, String),
+    #[error("Undefined symbol '{1}'")]
+    UndefinedSymbol(usize, String),
 
-    #[error("Unknown argument '{1}' at line {0}")]
+    #[error("'{1}' is already defined")]
+    DuplicateSymbol(usize, String),
+
+    #[error("Invalid name '{1}'")]
+    InvalidName(usize, String),
+
+    #[error("Unknown argument '{1}'")]
     UnknownArgument(usize, String),
+
+    #[error("Malformed string {1}")]
+    MalformedString(usize, String),
+
+    #[error("Malformed expression '{1}'")]
+    MalformedExpression(usize, String),
+
+    #[error("Division by zero in '{1}'")]
+    DivisionByZero(usize, String),
+
+    #[error("'{1}' is out of range")]
+    ValueOutOfRange(usize, String),
+
+    #[error("Unknown mnemonic '{1}'")]
+    UnknownMnemonic(usize, String),
+
+    #[error("Wrong number of arguments in '{1}'")]
+    ArgumentCount(usize, String),
+
+    #[error("Expression '{1}' is nested too deeply")]
+    NestingTooDeep(usize, String),
+
+    #[error("'{1}' doesn't fit in memory")]
+    ProgramTooLarge(usize, String),
+
+    #[error("Malformed macro '{1}'")]
+    MalformedMacro(usize, String),
+
+    #[error("Macro '{1}' has no '.endm'")]
+    UnterminatedMacro(usize, String),
+
+    #[error("Macro '{1}' is already defined")]
+    DuplicateMacro(usize, String),
+
+    #[error("Macro '{1}' is nested too deeply")]
+    MacroTooDeep(usize, String),
+
+    #[error("Macro '{1}' expands to too many lines")]
+    MacroTooLarge(usize, String),
+
+    #[error("Label '{1}' in a macro must be local")]
+    MacroLabel(usize, String),
+}
+
+impl AssemblerError {
+    /// Returns the source line the error occurred on, starting at 1
+    #[must_use]
+    pub fn line(&self) -> usize {
+        match self {
+            AssemblerError::MalformedAddress(line, _)
+            | AssemblerError::UnknownRegister(line, _)
+            | AssemblerError::MalformedNumber(line, _)
+            | AssemblerError::TypeMismatch(line, _)
+            | AssemblerError::LoadOpFail(line, _)
+            | AssemblerError::UndefinedSymbol(line, _)
+            | AssemblerError::DuplicateSymbol(line, _)
+            | AssemblerError::InvalidName(line, _)
+            | AssemblerError::UnknownArgument(line, _)
+            | AssemblerError::MalformedString(line, _)
+            | AssemblerError::MalformedExpression(line, _)
+            | AssemblerError::DivisionByZero(line, _)
+            | AssemblerError::ValueOutOfRange(line, _)
+            | AssemblerError::UnknownMnemonic(line, _)
+            | AssemblerError::ArgumentCount(line, _)
+            | AssemblerError::NestingTooDeep(line, _)
+            | AssemblerError::ProgramTooLarge(line, _)
+            | AssemblerError::MalformedMacro(line, _)
+            | AssemblerError::UnterminatedMacro(line, _)
+            | AssemblerError::DuplicateMacro(line, _)
+            | AssemblerError::MacroTooDeep(line, _)
+            | AssemblerError::MacroTooLarge(line, _)
+            | AssemblerError::MacroLabel(line, _) => line + 1,
+        }
+    }
+
+    /// Returns the source text or description the error refers to
+    #[must_use]
+    pub fn text(&self) -> &str {
+        match self {
+            AssemblerError::MalformedAddress(_, text)
+            | AssemblerError::UnknownRegister(_, text)
+            | AssemblerError::MalformedNumber(_, text)
+            | AssemblerError::TypeMismatch(_, text)
+            | AssemblerError::LoadOpFail(_, text)
+            | AssemblerError::UndefinedSymbol(_, text)
+            | AssemblerError::DuplicateSymbol(_, text)
+            | AssemblerError::InvalidName(_, text)
+            | AssemblerError::UnknownArgument(_, text)
+            | AssemblerError::MalformedString(_, text)
+            | AssemblerError::MalformedExpression(_, text)
+            | AssemblerError::DivisionByZero(_, text)
+            | AssemblerError::ValueOutOfRange(_, text)
+            | AssemblerError::UnknownMnemonic(_, text)
+            | AssemblerError::ArgumentCount(_, text)
+            | AssemblerError::NestingTooDeep(_, text)
+            | AssemblerError::ProgramTooLarge(_, text)
+            | AssemblerError::MalformedMacro(_, text)
+            | AssemblerError::UnterminatedMacro(_, text)
+            | AssemblerError::DuplicateMacro(_, text)
+            | AssemblerError::MacroTooDeep(_, text)
+            | AssemblerError::MacroTooLarge(_, text)
+            | AssemblerError::MacroLabel(_, text) => text,
+        }
+    }
 }
 
 pub const DEMO_SOURCE: &str = ".org 0x02           ; Offset start by 2
@@ -0,0 +1,111 @@
+//! Where each byte of an assembled program came from in the source
+
+use std::ops::Range;
+
+/// A statement in the source
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SourceSpan {
+    // Line and column start at 0, the column counts characters
+    line: usize,
+    column: usize,
+    // Byte offsets into the whole source
+    span: Range<usize>,
+}
+
+impl SourceSpan {
+    /// Lines and columns are counted from 0
+    #[must_use]
+    pub fn new(line: usize, column: usize, span: Range<usize>) -> Self {
+        SourceSpan { line, column, span }
+    }
+
+    /// Returns the source line, starting at 1
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.line + 1
+    }
+
+    /// Returns the column the statement starts at, counted in characters from 1
+    #[must_use]
+    pub fn column(&self) -> usize {
+        self.column + 1
+    }
+
+    /// Returns the byte offsets of the statement in the source
+    #[must_use]
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+/// Links addresses in a program to the statements which produced them, and back
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct SourceMap {
+    // In the order the statements were assembled, an address belongs to at most one entry
+    entries: Vec<(Range<usize>, SourceSpan)>,
+}
+
+impl SourceMap {
+    #[must_use]
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    /// Records the statement which produced the bytes at the addresses, bytes following on from
+    /// the same statement, such as a macro call, join its entry
+    pub fn insert(&mut self, addresses: Range<usize>, span: SourceSpan) {
+        if addresses.is_empty() {
+            return;
+        }
+
+        match self.entries.last_mut() {
+            Some((last, last_span)) if last.end == addresses.start && *last_span == span => {
+                last.end = addresses.end;
+            }
+            _ => self.entries.push((addresses, span)),
+        }
+    }
+
+    /// Forgets every address from `end` on, such as when `.org` moves back over them
+    pub fn truncate(&mut self, end: usize) {
+        self.entries.retain_mut(|(addresses, _)| {
+            addresses.end = addresses.end.min(end);
+            addresses.start < addresses.end
+        });
+    }
+
+    /// Returns the statement which produced the byte at the address
+    #[must_use]
+    pub fn span(&self, address: usize) -> Option<&SourceSpan> {
+        self.entries
+            .iter()
+            .find(|(addresses, _)| addresses.contains(&address))
+            .map(|(_, span)| span)
+    }
+
+    /// Returns the addresses produced by the statement on the line, lines start at 1
+    #[must_use]
+    pub fn addresses(&self, line: usize) -> Option<Range<usize>> {
+        self.entries
+            .iter()
+            .find(|(_, span)| span.line() == line)
+            .map(|(addresses, _)| addresses.clone())
+    }
+
+    /// Returns each statement with its addresses, in the order they were assembled
+    pub fn iter(&self) -> impl Iterator<Item = (Range<usize>, &SourceSpan)> {
+        self.entries
+            .iter()
+            .map(|(addresses, span)| (addresses.clone(), span))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What a name in the source refers to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SymbolKind {
+    /// An address in the program, `name:`
+    Label,
+
+    /// A number from `.equ`
+    Constant,
+
+    /// A register from `.reg`
+    Register,
+}
+
+/// A label, constant or register alias definition
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Symbol {
+    /// Address of a label, number of a constant or register of an alias
+    pub value: i64,
+
+    /// Source line the symbol is defined on, starting at 1 like `SourceSpan::line`
+    pub line: usize,
+
+    pub kind: SymbolKind,
+}
+
+/// Symbols defined in a program, sorted by name
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct SymbolTable {
+    symbols: BTreeMap<String, Symbol>,
+    // Lines each symbol is used on, starting at 1
+    references: BTreeMap<String, BTreeSet<usize>>,
+}
+
+impl SymbolTable {
+    #[must_use]
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// Adds a symbol, labels, constants and register aliases share the same names
+    ///
+    /// # Errors
+    ///
+    /// Returns the existing definition if the name is already defined
+    pub fn define(&mut self, name: &str, symbol: Symbol) -> Result<(), Symbol> {
+        match self.symbols.get(name) {
+            Some(existing) => Err(*existing),
+            None => {
+                self.symbols.insert(name.to_string(), symbol);
+                Ok(())
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    /// Returns the address, number or register of the symbol
+    #[must_use]
+    pub fn value(&self, name: &str) -> Option<i64> {
+        self.symbols.get(name).map(|symbol| symbol.value)
+    }
+
+    /// Records a use of the symbol on the line, lines start at 1
+    pub fn add_reference(&mut self, name: &str, line: usize) {
+        self.references
+            .entry(name.to_string())
+            .or_default()
+            .insert(line);
+    }
+
+    /// Returns the lines the symbol is used on in order, lines start at 1
+    pub fn references(&self, name: &str) -> impl Iterator<Item = usize> + '_ {
+        self.references.get(name).into_iter().flatten().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Symbol)> {
+        self.symbols
+            .iter()
+            .map(|(name, symbol)| (name.as_str(), symbol))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
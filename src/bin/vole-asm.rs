@@ -0,0 +1,224 @@
+//! Assemble a Vole program from the command line
+//!
+//! Builds without the `gui` feature: `cargo run --no-default-features --bin vole-asm -- program.asm`
+
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::Path,
+    process::ExitCode,
+};
+use vole_vm::{
+    asm::{assembler::Assembler, diagnostic::Diagnostic, listing::Listing},
+    rom::RomFormat,
+};
+
+const USAGE: &str = "Usage: vole-asm [OPTIONS] <FILE>
+
+Assembles FILE and writes the memory image.
+
+Options:
+  -o, --output <FILE>    Output file [default: standard output]
+  -f, --format <FORMAT>  bin, hex (hex dump), ihex (Intel HEX), contents (Address Contents),
+                         rust or c
+                         [default: from the output extension, otherwise hex]
+  --name <NAME>          Name of the rust or c array [default: ROM]
+  -l, --listing <FILE>   Also write a listing of addresses, bytes, source, symbols and
+                         cross-references
+  -h, --help             Print this message
+
+Errors, warnings and notes are printed as FILE:LINE:COLUMN: SEVERITY: MESSAGE.
+The exit status is 1 if there are any errors.";
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    file: String,
+    output: Option<String>,
+    format: RomFormat,
+    name: String,
+    listing: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("vole-asm: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let source = match fs::read_to_string(&options.file) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}: {e}", options.file);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match Assembler::new().assemble(source.clone()) {
+        Ok(result) => result,
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{}", format_diagnostic(&options.file, &source, diagnostic));
+            }
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for diagnostic in result.diagnostics() {
+        eprintln!("{}", format_diagnostic(&options.file, &source, diagnostic));
+    }
+
+    if let Some(path) = &options.listing {
+        let listing = Listing::new(&source, &result).to_string();
+        if let Err(e) = fs::write(path, listing) {
+            eprintln!("{path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let output = options.format.write(result.rom(), &options.name);
+    let written = match &options.output {
+        Some(path) => fs::write(path, output).map_err(|e| format!("{path}: {e}")),
+        None => io::stdout()
+            .write_all(&output)
+            .map_err(|e| format!("stdout: {e}")),
+    };
+
+    if let Err(e) = written {
+        eprintln!("{e}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Returns `None` if help was requested
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut file = None;
+    let mut output: Option<String> = None;
+    let mut format = None;
+    let mut name = "ROM".to_string();
+    let mut listing = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{name} requires a value"))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => output = Some(value(arg)?.clone()),
+            "-f" | "--format" => {
+                format = Some(match value(arg)?.as_str() {
+                    "bin" => RomFormat::Binary,
+                    "hex" => RomFormat::HexDump,
+                    "ihex" => RomFormat::IntelHex,
+                    "rust" => RomFormat::RustArray,
+                    "c" => RomFormat::CArray,
+                    "contents" => RomFormat::AddressContents,
+                    other => return Err(format!("Unknown format '{other}'")),
+                });
+            }
+            "--name" => name.clone_from(value(arg)?),
+            "-l" | "--listing" => listing = Some(value(arg)?.clone()),
+            other if other.starts_with('-') => return Err(format!("Unknown option '{other}'")),
+            other => {
+                if file.replace(other.to_string()).is_some() {
+                    return Err(format!("Unexpected argument '{other}'"));
+                }
+            }
+        }
+    }
+
+    let format = format
+        .or_else(|| {
+            let extension = Path::new(output.as_ref()?).extension()?;
+            RomFormat::from_extension(extension.to_str()?)
+        })
+        .unwrap_or(RomFormat::HexDump);
+
+    Ok(Some(Options {
+        file: file.ok_or("No source file given")?,
+        output,
+        format,
+        name,
+        listing,
+    }))
+}
+
+/// `file:line:column: severity: message`
+fn format_diagnostic(file: &str, source: &str, diagnostic: &Diagnostic) -> String {
+    format!(
+        "{file}:{}:{}: {diagnostic}",
+        diagnostic.line(),
+        diagnostic.column(source)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = parse_args(&args(&["prog.asm", "-o", "prog.hex"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.file, "prog.asm");
+        assert_eq!(options.format, RomFormat::IntelHex);
+
+        let options = parse_args(&args(&[
+            "-f", "c", "--name", "demo", "prog.asm", "-o", "a.bin",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(options.format, RomFormat::CArray);
+        assert_eq!(options.name, "demo");
+
+        let options = parse_args(&args(&["prog.asm"])).unwrap().unwrap();
+        assert_eq!(options.format, RomFormat::HexDump);
+        assert_eq!(options.output, None);
+        assert_eq!(options.listing, None);
+
+        let options = parse_args(&args(&["prog.asm", "-l", "prog.lst"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.listing.as_deref(), Some("prog.lst"));
+
+        assert!(parse_args(&args(&["-f", "elf", "prog.asm"])).is_err());
+        assert!(parse_args(&args(&["-o"])).is_err());
+        assert!(parse_args(&args(&[])).is_err());
+    }
+
+    #[test]
+    fn error_location() {
+        let source = "ld r0, 0x00\n    ld rz, 0x01\nhlt\n";
+        let diagnostics = Assembler::new().assemble(source.to_string()).unwrap_err();
+        let formatted: Vec<String> = diagnostics
+            .iter()
+            .map(|diagnostic| format_diagnostic("prog.asm", source, diagnostic))
+            .collect();
+
+        assert_eq!(
+            formatted,
+            [
+                "prog.asm:2:8: error: Unknown register 'rz'",
+                "prog.asm:3:1: error: Unknown mnemonic 'hlt'"
+            ]
+        );
+    }
+}
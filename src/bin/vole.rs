@@ -0,0 +1,423 @@
+//! Run a Vole program without the UI
+//!
+//! Builds without the `gui` feature: `cargo run --no-default-features --bin vole -- program.asm`
+
+use std::{fmt::Write as _, fs, path::Path, process::ExitCode};
+use vole_vm::{
+    asm::{assembler::Assembler, source_map::SourceMap},
+    rom::{RomFormat, parse_number},
+    vole::{
+        StartMode, Vole,
+        console::{CONSOLE_ADDRESS, CONSOLE_SIZE, Console},
+        debug::StopReason,
+        input::{INPUT_ADDRESS, INPUT_SIZE, Input},
+    },
+};
+
+const USAGE: &str = "Usage: vole [OPTIONS] <FILE>
+
+Assembles FILE if it ends in .asm or .s, otherwise loads it as a memory image,
+then runs it and prints the machine state. Images ending in .hex are read as Intel HEX,
+.contents as the Address Contents table, .txt as a hex dump and anything else as raw binary.
+
+Options:
+  --cycles <N>       Maximum number of cycles to run [default: 10000]
+  --start <PC>       Start address [default: the .org address, or 0x00 for binaries]
+  --input <TEXT>     Text queued for the input port
+  --trace            Print every instruction executed
+  --format <FORMAT>  Output format, text or json [default: text]
+  -h, --help         Print this message
+
+Exit status is 0 if the program halted, 1 if it stopped for another reason
+and 2 if it couldn't be loaded.";
+
+const DEFAULT_CYCLES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    file: String,
+    cycles: usize,
+    start: Option<u8>,
+    input: Vec<u8>,
+    trace: bool,
+    format: Format,
+}
+
+/// Machine state after the run
+struct Report {
+    reason: StopReason,
+    vole: Vole,
+    trace: Vec<String>,
+    // Source line of the instruction the machine stopped at, for assembled programs
+    line: Option<usize>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("vole: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let (rom, org, source_map) = match load_program(&options.file) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let report = match run(&options, &rom, org, &source_map) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {e}", options.file);
+            return ExitCode::from(2);
+        }
+    };
+
+    match options.format {
+        Format::Text => print!("{}", format_text(&report)),
+        Format::Json => println!("{}", format_json(&report)),
+    }
+
+    if report.reason == StopReason::Halted {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Returns `None` if help was requested
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut file = None;
+    let mut options = Options {
+        file: String::new(),
+        cycles: DEFAULT_CYCLES,
+        start: None,
+        input: Vec::new(),
+        trace: false,
+        format: Format::Text,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{name} requires a value"))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--trace" => options.trace = true,
+            "--cycles" => {
+                let cycles = value(arg)?;
+                options.cycles = parse_number(cycles)
+                    .ok_or_else(|| format!("Invalid cycle count '{cycles}'"))?;
+            }
+            "--start" => {
+                let start = value(arg)?;
+                let pc = parse_number(start)
+                    .and_then(|pc| u8::try_from(pc).ok())
+                    .ok_or_else(|| format!("Invalid start address '{start}'"))?;
+                options.start = Some(pc);
+            }
+            "--input" => options.input = value(arg)?.as_bytes().to_vec(),
+            "--format" => {
+                options.format = match value(arg)?.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    other => return Err(format!("Unknown format '{other}'")),
+                };
+            }
+            other if other.starts_with('-') => return Err(format!("Unknown option '{other}'")),
+            other => {
+                if file.replace(other.to_string()).is_some() {
+                    return Err(format!("Unexpected argument '{other}'"));
+                }
+            }
+        }
+    }
+
+    options.file = file.ok_or("No program file given")?;
+    Ok(Some(options))
+}
+
+/// Returns the memory image, the address execution starts at by default and where each address
+/// came from in the source
+fn load_program(file: &str) -> Result<(Vec<u8>, u8, SourceMap), String> {
+    let extension = Path::new(file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+
+    if extension.eq_ignore_ascii_case("asm") || extension.eq_ignore_ascii_case("s") {
+        let source = fs::read_to_string(file).map_err(|e| format!("{file}: {e}"))?;
+        let result = Assembler::new().assemble(source).map_err(|diagnostics| {
+            diagnostics
+                .iter()
+                .map(|d| format!("{file}:{}: {d}", d.line()))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })?;
+        Ok((
+            result.rom().to_vec(),
+            result.program_counter(),
+            result.source_map().clone(),
+        ))
+    } else {
+        let data = fs::read(file).map_err(|e| format!("{file}: {e}"))?;
+        let rom = RomFormat::from_extension(extension)
+            .unwrap_or(RomFormat::Binary)
+            .read(&data)
+            .map_err(|e| format!("{file}: {e}"))?;
+        Ok((rom, 0x00, SourceMap::new()))
+    }
+}
+
+fn run(options: &Options, rom: &[u8], org: u8, source_map: &SourceMap) -> Result<Report, String> {
+    let mut vole = Vole::new();
+    vole.bus_mut()
+        .map(
+            CONSOLE_ADDRESS..=CONSOLE_ADDRESS + CONSOLE_SIZE - 1,
+            Console::new(),
+        )
+        .map_err(|e| e.to_string())?;
+    vole.bus_mut()
+        .map(
+            INPUT_ADDRESS..=INPUT_ADDRESS + INPUT_SIZE - 1,
+            Input::with_bytes(&options.input),
+        )
+        .map_err(|e| e.to_string())?;
+
+    vole.load_rom(rom).map_err(|e| e.to_string())?;
+    vole.start(&StartMode::Reset, Some(options.start.unwrap_or(org)));
+
+    let mut trace = Vec::new();
+    let reason = if options.trace {
+        vole.enable_trace(1);
+
+        // Run a cycle at a time so every executed instruction is seen
+        let mut reason = StopReason::BudgetExhausted;
+        for _ in 0..options.cycles {
+            let cycles = vole.cycle_count();
+            reason = vole.run(1);
+
+            if vole.cycle_count() != cycles
+                && let Some(entry) = vole.trace().and_then(|trace| trace.last())
+            {
+                if options.format == Format::Text {
+                    println!("{entry}");
+                }
+                trace.push(entry.to_string());
+            }
+
+            if reason != StopReason::BudgetExhausted {
+                break;
+            }
+        }
+        reason
+    } else {
+        vole.run(options.cycles)
+    };
+
+    let address = reason.address(vole.program_counter());
+    let line = source_map.span(address.into()).map(|span| span.line());
+
+    Ok(Report {
+        reason,
+        vole,
+        trace,
+        line,
+    })
+}
+
+fn format_text(report: &Report) -> String {
+    let vole = &report.vole;
+    let mut out = String::new();
+
+    match report.line {
+        Some(line) => {
+            let _ = writeln!(out, "Stopped: {} on line {line}", report.reason);
+        }
+        None => {
+            let _ = writeln!(out, "Stopped: {}", report.reason);
+        }
+    }
+    let _ = writeln!(out, "Cycles: {}", vole.cycle_count());
+    let _ = writeln!(
+        out,
+        "PC: 0x{:02X}  IR: 0x{:04X}",
+        vole.program_counter(),
+        vole.instruction_register()
+    );
+
+    let _ = writeln!(out, "\nRegisters:");
+    for (i, row) in vole.registers().chunks(8).enumerate() {
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(r, value)| format!("r{:X}: 0x{value:02X}", i * 8 + r))
+            .collect();
+        let _ = writeln!(out, "  {}", cells.join("  "));
+    }
+
+    let _ = writeln!(out, "\nMemory:");
+    for (i, row) in vole.memory().chunks(16).enumerate() {
+        let cells: Vec<String> = row.iter().map(|value| format!("{value:02X}")).collect();
+        let _ = writeln!(out, "  0x{:02X}: {}", i * 16, cells.join(" "));
+    }
+
+    if let Some(console) = vole.bus().device::<Console>()
+        && !console.output().is_empty()
+    {
+        let _ = writeln!(out, "\nConsole:\n{}", console.output());
+    }
+
+    out
+}
+
+fn format_json(report: &Report) -> String {
+    let vole = &report.vole;
+    let console = vole
+        .bus()
+        .device::<Console>()
+        .map_or("", |console| console.output());
+
+    let bytes = |values: &[u8]| {
+        values
+            .iter()
+            .map(u8::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let trace: Vec<String> = report
+        .trace
+        .iter()
+        .map(|entry| json_string(entry))
+        .collect();
+
+    format!(
+        "{{\"stop_reason\":{},\"halted\":{},\"cycles\":{},\"pc\":{},\"ir\":{},\"registers\":[{}],\"memory\":[{}],\"console\":{},\"trace\":[{}],\"line\":{}}}",
+        json_string(&report.reason.to_string()),
+        report.reason == StopReason::Halted,
+        vole.cycle_count(),
+        vole.program_counter(),
+        vole.instruction_register(),
+        bytes(vole.registers()),
+        bytes(vole.memory()),
+        json_string(console),
+        trace.join(","),
+        report
+            .line
+            .map_or("null".to_string(), |line| line.to_string())
+    )
+}
+
+/// Quotes and escapes a string for JSON
+fn json_string(text: &str) -> String {
+    let mut out = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = parse_args(&args(&[
+            "--trace", "--start", "0x10", "prog.asm", "--format", "json", "--cycles", "50",
+        ]))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(options.file, "prog.asm");
+        assert_eq!(options.start, Some(0x10));
+        assert_eq!(options.cycles, 50);
+        assert_eq!(options.format, Format::Json);
+        assert!(options.trace);
+
+        assert_eq!(parse_args(&args(&["--help"])), Ok(None));
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["a", "b"])).is_err());
+        assert!(parse_args(&args(&["a", "--start", "0x100"])).is_err());
+        assert!(parse_args(&args(&["a", "--format", "xml"])).is_err());
+        assert!(parse_args(&args(&["a", "--cycles"])).is_err());
+    }
+
+    #[test]
+    fn run_and_report() {
+        let options = parse_args(&args(&["prog.bin", "--trace", "--format", "json"]))
+            .unwrap()
+            .unwrap();
+
+        // Print "A" to the console and halt
+        let rom = [0x21, 0x41, 0x31, 0xF0, 0xC0, 0x00];
+        let report = run(&options, &rom, 0x00, &SourceMap::new()).unwrap();
+        assert_eq!(report.reason, StopReason::Halted);
+        assert_eq!(report.trace.len(), 3);
+
+        let json = format_json(&report);
+        assert!(json.starts_with("{\"stop_reason\":\"Halted\",\"halted\":true,\"cycles\":3,"));
+        assert!(json.contains("\"registers\":[0,65,0,"));
+        assert!(json.contains("\"console\":\"A\""));
+
+        let text = format_text(&report);
+        assert!(text.contains("r1: 0x41"));
+        assert!(text.contains("  0x00: 21 41 31 F0 C0 00 00"));
+        assert!(text.ends_with("Console:\nA\n"));
+    }
+
+    #[test]
+    fn stop_line() {
+        let options = parse_args(&args(&["prog.asm"])).unwrap().unwrap();
+
+        let source = "ld r1, 0x01\n\n.db 0xD0, 0x12 ; Not an instruction\nhalt";
+        let result = Assembler::new().assemble(source.to_string()).unwrap();
+        let report = run(&options, result.rom(), 0x00, result.source_map()).unwrap();
+
+        assert!(matches!(report.reason, StopReason::Error(_)));
+        assert_eq!(report.line, Some(3));
+        assert!(
+            format_text(&report)
+                .starts_with("Stopped: Error: Invalid instruction 0xD012 on line 3\n")
+        );
+        assert!(format_json(&report).ends_with(",\"line\":3}"));
+    }
+
+    #[test]
+    fn escape_json() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+}
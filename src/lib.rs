@@ -7,9 +7,15 @@ pub mod vole;
 /// Simple assembler
 pub mod asm;
 
+/// Memory image file formats
+pub mod rom;
+
 /// UI
+#[cfg(feature = "gui")]
 mod ui;
+#[cfg(feature = "gui")]
 pub use ui::VoleUI;
 
 /// Storage key used for the app
+#[cfg(feature = "gui")]
 pub const APP_KEY: &str = "vole";
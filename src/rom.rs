@@ -0,0 +1,635 @@
+//! Text and binary formats for memory images
+
+use std::fmt::{self, Write as _};
+
+// Data bytes per Intel HEX record
+const INTEL_HEX_RECORD_SIZE: usize = 16;
+
+// Bytes per line of a hex dump
+const HEX_DUMP_LINE_SIZE: usize = 16;
+
+// Bytes per line of an array literal
+const ARRAY_LINE_SIZE: usize = 8;
+
+/// Formats a memory image can be written as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomFormat {
+    /// The bytes as they are
+    Binary,
+
+    /// Address followed by the bytes in hexadecimal
+    HexDump,
+
+    /// Intel HEX records
+    IntelHex,
+
+    /// Rust `const` array
+    RustArray,
+
+    /// C `const unsigned char` array
+    CArray,
+
+    /// The "Address Contents" table from the export window, one instruction per row in hex
+    AddressContents,
+}
+
+/// Rows of the "Address Contents" table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentsLayout {
+    /// A row per byte
+    Bytes,
+
+    /// A row per two byte instruction
+    Instructions,
+}
+
+/// How numbers in the "Address Contents" table are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentsRadix {
+    /// `0x` prefixed hexadecimal
+    Hex,
+
+    /// `0b` prefixed binary
+    Binary,
+}
+
+impl ContentsRadix {
+    fn byte(self, value: u8) -> String {
+        match self {
+            ContentsRadix::Hex => format!("0x{value:02X}"),
+            ContentsRadix::Binary => format!("{value:#010b}"),
+        }
+    }
+
+    fn word(self, value: u16) -> String {
+        match self {
+            ContentsRadix::Hex => format!("0x{value:04X}"),
+            ContentsRadix::Binary => format!("{value:#018b}"),
+        }
+    }
+}
+
+/// Errors which occur when reading a memory image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    /// The file isn't valid UTF-8 text
+    NotText,
+
+    /// The line can't be parsed
+    Syntax(usize),
+
+    /// An Intel HEX record's checksum doesn't match its contents
+    Checksum {
+        /// Line of the record
+        line: usize,
+
+        /// Checksum of the contents
+        expected: u8,
+
+        /// Checksum in the record
+        found: u8,
+    },
+
+    /// An Intel HEX record type which isn't supported
+    UnsupportedRecord {
+        /// Line of the record
+        line: usize,
+
+        /// Record type
+        record_type: u8,
+    },
+
+    /// The line places data above address 0xFFFF
+    AddressOutOfRange(usize),
+
+    /// The Intel HEX end of file record is missing
+    MissingEndOfFile,
+
+    /// The format can be written but not read
+    WriteOnly(RomFormat),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::NotText => write!(f, "The file isn't text"),
+            RomError::Syntax(line) => write!(f, "Line {line} can't be read"),
+            RomError::Checksum {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Line {line} has the checksum 0x{found:02X}, expected 0x{expected:02X}"
+            ),
+            RomError::UnsupportedRecord { line, record_type } => {
+                write!(
+                    f,
+                    "Line {line} has the unsupported record type 0x{record_type:02X}"
+                )
+            }
+            RomError::AddressOutOfRange(line) => {
+                write!(f, "Line {line} has an address above 0xFFFF")
+            }
+            RomError::MissingEndOfFile => write!(f, "The end of file record is missing"),
+            RomError::WriteOnly(format) => write!(f, "{format:?} files can't be read"),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+impl RomFormat {
+    /// Returns the format usually stored with the file extension
+    #[must_use]
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "bin" | "rom" => Some(RomFormat::Binary),
+            "txt" => Some(RomFormat::HexDump),
+            "hex" | "ihex" => Some(RomFormat::IntelHex),
+            "rs" => Some(RomFormat::RustArray),
+            "c" | "h" => Some(RomFormat::CArray),
+            "contents" => Some(RomFormat::AddressContents),
+            _ => None,
+        }
+    }
+
+    /// Write the image in this format, arrays are given the name
+    #[must_use]
+    pub fn write(&self, rom: &[u8], name: &str) -> Vec<u8> {
+        match self {
+            RomFormat::Binary => rom.to_vec(),
+            RomFormat::HexDump => to_hex_dump(rom).into_bytes(),
+            RomFormat::IntelHex => to_intel_hex(rom).into_bytes(),
+            RomFormat::RustArray => to_rust_array(rom, name).into_bytes(),
+            RomFormat::CArray => to_c_array(rom, name).into_bytes(),
+            RomFormat::AddressContents => {
+                to_address_contents(rom, ContentsLayout::Instructions, ContentsRadix::Hex)
+                    .into_bytes()
+            }
+        }
+    }
+
+    /// Read a memory image starting at address 0, gaps are filled with zero.
+    ///
+    /// The image ends at the last byte in the file, rows of the "Address Contents" table
+    /// which are zero aren't written so trailing zeros are lost.
+    ///
+    /// # Errors
+    ///
+    /// Will return `RomError` if the data can't be read in this format.
+    pub fn read(&self, data: &[u8]) -> Result<Vec<u8>, RomError> {
+        if *self == RomFormat::Binary {
+            return Ok(data.to_vec());
+        }
+
+        let text = std::str::from_utf8(data).map_err(|_| RomError::NotText)?;
+        match self {
+            RomFormat::HexDump => from_hex_dump(text),
+            RomFormat::IntelHex => from_intel_hex(text),
+            RomFormat::AddressContents => from_address_contents(text),
+            _ => Err(RomError::WriteOnly(*self)),
+        }
+    }
+}
+
+/// Lines of `0xAA: BB BB ..` with 16 bytes per line
+#[must_use]
+pub fn to_hex_dump(rom: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in rom.chunks(HEX_DUMP_LINE_SIZE).enumerate() {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("{byte:02X}")).collect();
+        let _ = writeln!(out, "0x{:02X}: {}", i * HEX_DUMP_LINE_SIZE, bytes.join(" "));
+    }
+    out
+}
+
+/// Reads lines of `0xAA: BB BB ..`
+///
+/// # Errors
+///
+/// Will return `RomError` if a line can't be parsed.
+pub fn from_hex_dump(text: &str) -> Result<Vec<u8>, RomError> {
+    let mut image = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (address, bytes) = line.split_once(':').ok_or(RomError::Syntax(line_number))?;
+        let address = parse_number(address.trim()).ok_or(RomError::Syntax(line_number))?;
+
+        for (offset, byte) in bytes.split_whitespace().enumerate() {
+            let byte = u8::from_str_radix(byte, 16).map_err(|_| RomError::Syntax(line_number))?;
+            put(&mut image, address + offset, byte, line_number)?;
+        }
+    }
+
+    Ok(image)
+}
+
+/// Data records of 16 bytes starting at address 0, followed by the end of file record
+#[must_use]
+pub fn to_intel_hex(rom: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, data) in rom.chunks(INTEL_HEX_RECORD_SIZE).enumerate() {
+        let address = i * INTEL_HEX_RECORD_SIZE;
+        write_intel_hex_record(&mut out, address as u16, 0x00, data);
+    }
+    write_intel_hex_record(&mut out, 0, 0x01, &[]);
+    out
+}
+
+fn write_intel_hex_record(out: &mut String, address: u16, record_type: u8, data: &[u8]) {
+    let [address_high, address_low] = address.to_be_bytes();
+    let header = [data.len() as u8, address_high, address_low, record_type];
+
+    // Two's complement of the sum of every byte in the record
+    let sum = header
+        .iter()
+        .chain(data)
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    let checksum = sum.wrapping_neg();
+
+    out.push(':');
+    for byte in header.iter().chain(data).chain([&checksum]) {
+        let _ = write!(out, "{byte:02X}");
+    }
+    out.push('\n');
+}
+
+/// Reads data records, extended address records must be zero and start address records are
+/// ignored.
+///
+/// # Errors
+///
+/// Will return `RomError` if a record is malformed or unsupported, or the end of file record is
+/// missing.
+pub fn from_intel_hex(text: &str) -> Result<Vec<u8>, RomError> {
+    let mut image = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let record = line
+            .strip_prefix(':')
+            .and_then(decode_hex)
+            .ok_or(RomError::Syntax(line_number))?;
+
+        // Byte count, two address bytes, record type, data, checksum
+        let count = *record.first().ok_or(RomError::Syntax(line_number))? as usize;
+        if record.len() != count + 5 {
+            return Err(RomError::Syntax(line_number));
+        }
+
+        let (contents, checksum) = record.split_at(record.len() - 1);
+        let expected = contents
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+            .wrapping_neg();
+        if expected != checksum[0] {
+            return Err(RomError::Checksum {
+                line: line_number,
+                expected,
+                found: checksum[0],
+            });
+        }
+
+        let address = usize::from(u16::from_be_bytes([record[1], record[2]]));
+        let data = &contents[4..];
+        match record[3] {
+            0x00 => {
+                for (offset, byte) in data.iter().enumerate() {
+                    put(&mut image, address + offset, *byte, line_number)?;
+                }
+            }
+            0x01 => return Ok(image),
+            0x02 | 0x04 => {
+                if data.iter().any(|byte| *byte != 0) {
+                    return Err(RomError::AddressOutOfRange(line_number));
+                }
+            }
+            0x03 | 0x05 => {}
+            record_type => {
+                return Err(RomError::UnsupportedRecord {
+                    line: line_number,
+                    record_type,
+                });
+            }
+        }
+    }
+
+    Err(RomError::MissingEndOfFile)
+}
+
+/// The "Address Contents" table from the export window, rows which are zero are skipped
+#[must_use]
+pub fn to_address_contents(rom: &[u8], layout: ContentsLayout, radix: ContentsRadix) -> String {
+    let mut out = String::from("Address         Contents\n");
+
+    for (i, chunk) in rom.chunks(2).enumerate() {
+        let high = chunk[0];
+        let low = chunk.get(1).copied().unwrap_or(0);
+        if high == 0 && low == 0 {
+            continue;
+        }
+
+        let a0 = radix.byte((i * 2) as u8);
+        let a1 = radix.byte((i * 2 + 1) as u8);
+        match layout {
+            ContentsLayout::Bytes => {
+                let spacing = "            ";
+                let _ = writeln!(out, "{a0}{spacing}{}", radix.byte(high));
+                let _ = writeln!(out, "{a1}{spacing}{}", radix.byte(low));
+            }
+            ContentsLayout::Instructions => {
+                let word = radix.word(u16::from_be_bytes([high, low]));
+                let _ = writeln!(out, "{a0}-{a1}       {word}");
+            }
+        }
+    }
+
+    out
+}
+
+/// Reads the "Address Contents" table in either layout and radix
+///
+/// # Errors
+///
+/// Will return `RomError` if a row can't be parsed.
+pub fn from_address_contents(text: &str) -> Result<Vec<u8>, RomError> {
+    let mut image = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("Address") {
+            continue;
+        }
+
+        let syntax = RomError::Syntax(line_number);
+        let mut columns = line.split_whitespace();
+        let (Some(address), Some(contents), None) =
+            (columns.next(), columns.next(), columns.next())
+        else {
+            return Err(syntax);
+        };
+        let contents = parse_number(contents).ok_or(syntax.clone())?;
+
+        match address.split_once('-') {
+            Some((first, second)) => {
+                let first = parse_number(first).ok_or(syntax.clone())?;
+                let second = parse_number(second).ok_or(syntax.clone())?;
+                let word = u16::try_from(contents).map_err(|_| syntax.clone())?;
+                if second != first + 1 {
+                    return Err(syntax);
+                }
+
+                let [high, low] = word.to_be_bytes();
+                put(&mut image, first, high, line_number)?;
+                put(&mut image, second, low, line_number)?;
+            }
+            None => {
+                let address = parse_number(address).ok_or(syntax.clone())?;
+                let byte = u8::try_from(contents).map_err(|_| syntax)?;
+                put(&mut image, address, byte, line_number)?;
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+/// Parses a `0x` hexadecimal, `0b` binary or decimal number, the prefix can be upper case.
+///
+/// Shared by the file formats, the assembler and the command line so they read numbers alike.
+#[must_use]
+pub fn parse_number(text: &str) -> Option<usize> {
+    let (digits, radix) = match text.get(..2) {
+        Some("0x" | "0X") => (&text[2..], 16),
+        Some("0b" | "0B") => (&text[2..], 2),
+        _ => (text, 10),
+    };
+
+    usize::from_str_radix(digits, radix).ok()
+}
+
+/// Decodes pairs of hexadecimal digits
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Write a byte into the image, growing it as needed
+fn put(image: &mut Vec<u8>, address: usize, byte: u8, line: usize) -> Result<(), RomError> {
+    if address > usize::from(u16::MAX) {
+        return Err(RomError::AddressOutOfRange(line));
+    }
+
+    if image.len() <= address {
+        image.resize(address + 1, 0);
+    }
+    image[address] = byte;
+    Ok(())
+}
+
+/// `pub const NAME: [u8; N] = [..];`
+#[must_use]
+pub fn to_rust_array(rom: &[u8], name: &str) -> String {
+    format!(
+        "pub const {name}: [u8; {}] = [\n{}];\n",
+        rom.len(),
+        array_body(rom)
+    )
+}
+
+/// `const unsigned char name[N] = {..};`
+#[must_use]
+pub fn to_c_array(rom: &[u8], name: &str) -> String {
+    format!(
+        "const unsigned char {name}[{}] = {{\n{}}};\n",
+        rom.len(),
+        array_body(rom)
+    )
+}
+
+fn array_body(rom: &[u8]) -> String {
+    let mut out = String::new();
+    for line in rom.chunks(ARRAY_LINE_SIZE) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("0x{byte:02X},")).collect();
+        let _ = writeln!(out, "    {}", bytes.join(" "));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::{DEMO_ROM, DEMO_SOURCE, assembler::Assembler};
+
+    const READABLE: [RomFormat; 4] = [
+        RomFormat::Binary,
+        RomFormat::HexDump,
+        RomFormat::IntelHex,
+        RomFormat::AddressContents,
+    ];
+
+    #[test]
+    fn round_trip() {
+        let demo = Assembler::new().assemble(DEMO_SOURCE.to_string()).unwrap();
+        assert_eq!(demo.program_counter(), 0x02);
+
+        // A program placed high in memory, past the first Intel HEX record
+        let high = Assembler::new()
+            .assemble(".org 0x40\nld r1, 0x2A\nld (0x80), r1\nhalt\n".to_string())
+            .unwrap();
+        assert_eq!(high.rom().len(), 0x46);
+
+        let full: Vec<u8> = (0..=255).collect();
+
+        for rom in [demo.rom(), high.rom(), DEMO_ROM, &full] {
+            for format in READABLE {
+                let written = format.write(rom, "ROM");
+                assert_eq!(format.read(&written).unwrap(), rom, "{format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn address_contents() {
+        let rom = [0x00, 0x00, 0x20, 0x0F, 0xC0, 0x00];
+
+        let bytes = to_address_contents(&rom, ContentsLayout::Bytes, ContentsRadix::Hex);
+        assert_eq!(
+            bytes,
+            "Address         Contents\n\
+             0x02            0x20\n\
+             0x03            0x0F\n\
+             0x04            0xC0\n\
+             0x05            0x00\n"
+        );
+
+        let words = to_address_contents(&rom, ContentsLayout::Instructions, ContentsRadix::Binary);
+        assert_eq!(
+            words.lines().nth(1),
+            Some("0b00000010-0b00000011       0b0010000000001111")
+        );
+
+        for text in [bytes, words] {
+            assert_eq!(from_address_contents(&text).unwrap(), rom);
+        }
+
+        assert_eq!(
+            from_address_contents("0x02-0x04 0x2000"),
+            Err(RomError::Syntax(1))
+        );
+        assert_eq!(
+            from_address_contents("Address Contents\n0x02 0x100"),
+            Err(RomError::Syntax(2))
+        );
+    }
+
+    #[test]
+    fn intel_hex_errors() {
+        assert_eq!(
+            from_intel_hex(":0100000020DF\n:00000001FF\n"),
+            Ok(vec![0x20])
+        );
+        assert_eq!(
+            from_intel_hex(":0100000020DE\n"),
+            Err(RomError::Checksum {
+                line: 1,
+                expected: 0xDF,
+                found: 0xDE
+            })
+        );
+        assert_eq!(
+            from_intel_hex(":0100000020DF\n"),
+            Err(RomError::MissingEndOfFile)
+        );
+        assert_eq!(from_intel_hex("0100000020DF\n"), Err(RomError::Syntax(1)));
+        assert_eq!(from_intel_hex(":02000000200\n"), Err(RomError::Syntax(1)));
+        assert_eq!(
+            from_intel_hex(":020000040001F9\n"),
+            Err(RomError::AddressOutOfRange(1))
+        );
+        assert_eq!(
+            from_intel_hex(":00000006FA\n"),
+            Err(RomError::UnsupportedRecord {
+                line: 1,
+                record_type: 0x06
+            })
+        );
+        assert_eq!(
+            RomFormat::RustArray.read(b"[]"),
+            Err(RomError::WriteOnly(RomFormat::RustArray))
+        );
+    }
+
+    #[test]
+    fn intel_hex() {
+        let rom: Vec<u8> = (0..20).collect();
+        assert_eq!(
+            to_intel_hex(&rom),
+            ":10000000000102030405060708090A0B0C0D0E0F78\n\
+             :0400100010111213A6\n\
+             :00000001FF\n"
+        );
+        assert_eq!(to_intel_hex(&[]), ":00000001FF\n");
+    }
+
+    #[test]
+    fn text_formats() {
+        let rom = [0x20, 0x00, 0x25, 0xFF, 0x14, 0x44, 0xB4, 0x0C, 0xC0, 0x00];
+
+        assert_eq!(to_hex_dump(&rom), "0x00: 20 00 25 FF 14 44 B4 0C C0 00\n");
+        assert_eq!(
+            to_rust_array(&rom, "PROGRAM"),
+            "pub const PROGRAM: [u8; 10] = [\n    \
+             0x20, 0x00, 0x25, 0xFF, 0x14, 0x44, 0xB4, 0x0C,\n    \
+             0xC0, 0x00,\n];\n"
+        );
+        assert_eq!(
+            to_c_array(&rom[..2], "program"),
+            "const unsigned char program[2] = {\n    0x20, 0x00,\n};\n"
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("0x1F"), Some(0x1F));
+        assert_eq!(parse_number("0XFF"), Some(0xFF));
+        assert_eq!(parse_number("0b101"), Some(0b101));
+        assert_eq!(parse_number("0B11"), Some(0b11));
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("12ab"), None);
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(RomFormat::from_extension("BIN"), Some(RomFormat::Binary));
+        assert_eq!(RomFormat::from_extension("hex"), Some(RomFormat::IntelHex));
+        assert_eq!(RomFormat::from_extension("h"), Some(RomFormat::CArray));
+        assert_eq!(RomFormat::from_extension("asm"), None);
+        // `.lst` is left to assembler listings
+        assert_eq!(
+            RomFormat::from_extension("contents"),
+            Some(RomFormat::AddressContents)
+        );
+        assert_eq!(RomFormat::from_extension("lst"), None);
+        assert_eq!(RomFormat::Binary.write(&[1, 2], "ROM"), [1, 2]);
+    }
+}
@@ -1,10 +1,26 @@
 use super::{cycle::CycleExecutionMode, numeric::NumericDisplay, rom::Rom, source::SourceEditMode};
 use crate::{
-    asm::{assembler::Assembler, AssemblerError, DEMO_ROM, DEMO_SOURCE},
+    asm::{
+        DEMO_ROM, DEMO_SOURCE,
+        assembler::Assembler,
+        diagnostic::{Diagnostics, Severity},
+        disassembler::disassemble_with_data,
+        listing::Listing,
+        source_map::SourceMap,
+    },
+    rom::{ContentsLayout, ContentsRadix, RomFormat, to_address_contents},
     ui::help,
-    vole::{StartMode, Vole},
+    vole::{
+        LoadError, ProgramCounterPolicy, StartMode, Vole,
+        bus::MapError,
+        console::{CONSOLE_ADDRESS, CONSOLE_DATA, CONSOLE_SIZE, CONSOLE_STATUS, Console},
+        debug::{StopReason, Watchpoint},
+        framebuffer::{FRAMEBUFFER_ADDRESS, FRAMEBUFFER_SIZE, Framebuffer},
+        history::HistoryError,
+        input::{INPUT_ADDRESS, INPUT_DATA, INPUT_SIZE, INPUT_STATUS, Input},
+    },
 };
-use egui::{scroll_area::ScrollBarVisibility, Color32, Vec2};
+use egui::{Color32, Vec2, scroll_area::ScrollBarVisibility};
 use egui_code_editor::{CodeEditor, ColorTheme, Syntax};
 use regex::Regex;
 use strum::IntoEnumIterator;
@@ -15,6 +31,17 @@ const BINARY_STR: &str = "\\b(0b)?[01]+\\b";
 // TODO: Add color picker
 const COLOR_PC: Color32 = Color32::ORANGE;
 const COLOR_IR: Color32 = Color32::GREEN;
+const COLOR_PIXEL_ON: Color32 = Color32::LIGHT_GREEN;
+const COLOR_PIXEL_OFF: Color32 = Color32::BLACK;
+
+// Size of a framebuffer pixel on screen
+const PIXEL_SIZE: f32 = 16.0;
+
+// Number of cycles kept in the execution trace
+const TRACE_CAPACITY: usize = 64;
+
+// Number of cycles which can be stepped back through
+const HISTORY_CAPACITY: usize = 4096;
 
 // TODO: Add a container for marking elements to be highlighted or animated with a timer component
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
@@ -27,6 +54,10 @@ pub struct VoleUI {
     rom: Rom,
     execution_mode: CycleExecutionMode,
     program_counter: u8,
+    wrap_program_counter: bool,
+    framebuffer_enabled: bool,
+    framebuffer_address: u8,
+    rom_path: String,
 
     #[serde(skip)]
     active_cell_index: Option<usize>,
@@ -49,17 +80,63 @@ pub struct VoleUI {
     #[serde(skip)]
     show_help: bool,
 
+    #[serde(skip)]
+    show_rom_file: bool,
+
+    #[serde(skip)]
+    rom_import_text: String,
+
+    #[serde(skip)]
+    rom_file_status: Option<String>,
+
     #[serde(skip)]
     cycle_timer: f32,
 
     #[serde(skip)]
     assembler: Assembler,
 
+    // Listing of the last successful compile
+    #[serde(skip)]
+    compiled_listing: String,
+
+    #[serde(skip)]
+    compilation_diagnostics: Diagnostics,
+
+    // The source the program in memory was compiled from
+    #[serde(skip)]
+    compiled_code: String,
+
+    // A line picked from the memory grid, with the program counter when it was picked
+    #[serde(skip)]
+    selected_line: Option<(usize, u8)>,
+
+    #[serde(skip)]
+    paused: bool,
+
+    #[serde(skip)]
+    stop_reason: Option<StopReason>,
+
+    #[serde(skip)]
+    load_error: Option<LoadError>,
+
+    // Why the timeline couldn't move the machine to the picked cycle
+    #[serde(skip)]
+    timeline_error: Option<HistoryError>,
+
+    #[serde(skip)]
+    breakpoint_address: u8,
+
+    #[serde(skip)]
+    watch_address: u8,
+
     #[serde(skip)]
-    compiled_source: Vec<u8>,
+    watch_register: u8,
 
     #[serde(skip)]
-    compilation_error: Option<AssemblerError>,
+    input_text: String,
+
+    #[serde(skip)]
+    framebuffer_error: Option<MapError>,
 }
 
 impl Default for VoleUI {
@@ -71,17 +148,48 @@ impl Default for VoleUI {
             rom: Rom::new(),
             execution_mode: CycleExecutionMode::Manual(false),
             program_counter: 0,
+            wrap_program_counter: true,
+            framebuffer_enabled: true,
+            framebuffer_address: FRAMEBUFFER_ADDRESS,
+            rom_path: "program.hex".to_owned(),
             active_cell_index: None,
             active_cell_string: "".to_owned(),
             hex_regex: Regex::new(HEX_STR).expect("Hex regex failed to be created"),
             binary_regex: Regex::new(BINARY_STR).expect("Binary regex failed to be created"),
-            vole: Vole::new(),
+            vole: {
+                let mut vole = Vole::new();
+                vole.enable_history(HISTORY_CAPACITY);
+                vole.bus_mut()
+                    .map(
+                        CONSOLE_ADDRESS..=CONSOLE_ADDRESS + CONSOLE_SIZE - 1,
+                        Console::new(),
+                    )
+                    .expect("Console failed to be mapped");
+                vole.bus_mut()
+                    .map(INPUT_ADDRESS..=INPUT_ADDRESS + INPUT_SIZE - 1, Input::new())
+                    .expect("Input failed to be mapped");
+                vole
+            },
             show_export: false,
             show_help: false,
+            show_rom_file: false,
+            rom_import_text: String::new(),
+            rom_file_status: None,
             cycle_timer: 0.0,
             assembler: Assembler::new(),
-            compiled_source: Vec::new(),
-            compilation_error: None,
+            compiled_listing: String::new(),
+            compilation_diagnostics: Diagnostics::new(),
+            compiled_code: String::new(),
+            selected_line: None,
+            paused: false,
+            stop_reason: None,
+            load_error: None,
+            timeline_error: None,
+            breakpoint_address: 0,
+            watch_address: 0,
+            watch_register: 0,
+            input_text: String::new(),
+            framebuffer_error: None,
         }
     }
 }
@@ -97,11 +205,157 @@ impl VoleUI {
 
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, crate::APP_KEY).unwrap_or_default();
+        let mut app: Self = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, crate::APP_KEY))
+            .unwrap_or_default();
+
+        // The machine isn't persisted, map the display where it was saved, keeping it within memory
+        app.framebuffer_address = app.framebuffer_address.min(u8::MAX - FRAMEBUFFER_SIZE + 1);
+        app.map_framebuffer();
+        app
+    }
+
+    /// Map the framebuffer at the configured address, or remove it if it's disabled
+    fn map_framebuffer(&mut self) {
+        let bus = self.vole.bus_mut();
+        if let Some(range) = bus.device_range::<Framebuffer>() {
+            bus.unmap(*range.start());
+        }
+
+        self.framebuffer_error = None;
+        if self.framebuffer_enabled {
+            let start = self.framebuffer_address;
+            if let Err(e) = bus.map(start..=start + FRAMEBUFFER_SIZE - 1, Framebuffer::new()) {
+                self.framebuffer_error = Some(e);
+            }
+        }
+    }
+
+    /// The line picked from the memory grid, otherwise the line being executed, counted from 0
+    ///
+    /// Nothing is highlighted once the source no longer matches the program in memory.
+    fn highlighted_line(&self) -> Option<usize> {
+        if self.source_code != self.compiled_code {
+            return None;
+        }
+
+        let pc = self.vole.program_counter();
+        if let Some((line, selected_at)) = self.selected_line
+            && selected_at == pc
+        {
+            return Some(line);
+        }
+
+        let address = match &self.stop_reason {
+            Some(reason) => reason.address(pc),
+            None if self.vole.running() => pc,
+            None => return None,
+        };
+        self.rom
+            .source_map()
+            .span(address.into())
+            .map(|span| span.line() - 1)
+    }
+
+    /// Replace the program with a memory image, returns a status message
+    fn set_rom_image(&mut self, image: &[u8]) -> String {
+        let memory = self.vole.memory().len();
+        if image.len() > memory {
+            return format!(
+                "The image is {} bytes, memory is {memory} bytes",
+                image.len()
+            );
         }
 
-        Default::default()
+        let mut bytes = vec![0; memory];
+        bytes[..image.len()].copy_from_slice(image);
+        self.rom.set_bytes(&bytes);
+
+        // Show the bytes which were loaded
+        if self.source_edit_mode == SourceEditMode::Assembly {
+            self.source_edit_mode = SourceEditMode::Instruction;
+        }
+
+        format!("Loaded {} bytes", image.len())
+    }
+
+    /// Returns the format for the file at the path, raw binary if the extension is unknown
+    #[cfg(not(target_arch = "wasm32"))]
+    fn rom_file_format(&self) -> RomFormat {
+        std::path::Path::new(&self.rom_path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(RomFormat::from_extension)
+            .unwrap_or(RomFormat::Binary)
+    }
+
+    /// Read the program from the file at the path, returns a status message
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_rom_file(&mut self) -> String {
+        let image = std::fs::read(&self.rom_path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                self.rom_file_format()
+                    .read(&data)
+                    .map_err(|e| e.to_string())
+            });
+
+        match image {
+            Ok(image) => self.set_rom_image(&image),
+            Err(e) => format!("Open failed: {e}"),
+        }
+    }
+
+    /// Write the program to the file at the path, returns a status message
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_rom_file(&self) -> String {
+        let data = self.rom_file_format().write(self.rom.bytes(), "ROM");
+        match std::fs::write(&self.rom_path, data) {
+            Ok(()) => format!("Saved {}", self.rom_path),
+            Err(e) => format!("Save failed: {e}"),
+        }
+    }
+
+    /// Read the program from the pasted text, returns a status message
+    fn import_rom_text(&mut self) -> String {
+        let text = self.rom_import_text.trim();
+        let first_line = text.lines().next().unwrap_or_default();
+
+        let format = if text.starts_with(':') {
+            RomFormat::IntelHex
+        } else if first_line.contains(':') {
+            RomFormat::HexDump
+        } else {
+            RomFormat::AddressContents
+        };
+
+        match format.read(text.as_bytes()) {
+            Ok(image) => self.set_rom_image(&image),
+            Err(e) => format!("Import failed: {e}"),
+        }
+    }
+
+    /// Load the rom into the machine and start it from the program counter
+    fn start_program(&mut self) {
+        self.paused = false;
+        self.stop_reason = None;
+        self.timeline_error = None;
+
+        if let Err(e) = self.vole.load_rom(self.rom.bytes()) {
+            self.load_error = Some(e);
+            return;
+        }
+        self.load_error = None;
+
+        self.vole
+            .set_program_counter_policy(if self.wrap_program_counter {
+                ProgramCounterPolicy::Wrap
+            } else {
+                ProgramCounterPolicy::Error
+            });
+        self.vole
+            .start(&StartMode::Reset, Some(self.program_counter));
     }
 }
 
@@ -113,32 +367,35 @@ impl eframe::App for VoleUI {
 
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.vole.running() {
-            match self.execution_mode {
-                CycleExecutionMode::FullSpeed => {
-                    if let Err(e) = self.vole.cycle() {
-                        // TODO: Push to ui
-                        println!("Error: {:?}", e)
-                    }
-                }
+        if self.vole.running() && !self.paused {
+            let budget = match self.execution_mode {
+                CycleExecutionMode::FullSpeed => 1,
                 CycleExecutionMode::Timer(limit) => {
                     self.cycle_timer += 1.0 / 60.0;
 
                     if self.cycle_timer >= limit {
                         self.cycle_timer = 0.0;
-                        if let Err(e) = self.vole.cycle() {
-                            // TODO: Push to ui
-                            println!("Error: {:?}", e)
-                        }
+                        1
+                    } else {
+                        0
                     }
                 }
                 CycleExecutionMode::Manual(step) => {
-                    if step {
-                        if let Err(e) = self.vole.cycle() {
-                            // TODO: Push to ui
-                            println!("Error: {:?}", e)
-                        }
-                        self.execution_mode = CycleExecutionMode::Manual(false);
+                    self.execution_mode = CycleExecutionMode::Manual(false);
+                    usize::from(step)
+                }
+            };
+
+            if budget > 0 {
+                match self.vole.run(budget) {
+                    StopReason::BudgetExhausted => {}
+                    reason @ (StopReason::Breakpoint(_) | StopReason::Watchpoint(_)) => {
+                        // Pause so the state can be inspected
+                        self.paused = true;
+                        self.stop_reason = Some(reason);
+                    }
+                    reason => {
+                        self.stop_reason = Some(reason);
                     }
                 }
             }
@@ -221,10 +478,22 @@ impl eframe::App for VoleUI {
                             }
                             _ => {
                                 self.rom.bytes_mut()[0..DEMO_ROM.len()].copy_from_slice(DEMO_ROM);
+                                self.rom.set_data(&[]);
+                                self.rom.set_source_map(SourceMap::new());
                             }
                         }
                     }
 
+                    if self.source_edit_mode != SourceEditMode::Assembly
+                        && ui
+                            .button("Disassemble")
+                            .on_hover_text("Convert the program into assembly source")
+                            .clicked()
+                    {
+                        self.source_code = disassemble_with_data(self.rom.bytes(), self.rom.data());
+                        self.source_edit_mode = SourceEditMode::Assembly;
+                    }
+
                     ui.separator();
 
                     // TODO: Add proper modes
@@ -232,6 +501,7 @@ impl eframe::App for VoleUI {
                     match self.source_edit_mode {
                         // TODO: Merge with instruction mode
                         SourceEditMode::Byte => {
+                            let mut edited = false;
                             egui::ScrollArea::vertical()
                                 .max_height(300.0)
                                 .auto_shrink(false)
@@ -303,11 +573,13 @@ impl eframe::App for VoleUI {
                                                             self.active_cell_string = byte_string;
                                                         }
                                                     } else if response.lost_focus() {
-                                                        *byte = u8::from_str_radix(
+                                                        let value = u8::from_str_radix(
                                                             byte_string.trim_start_matches(prefix),
                                                             self.numeric_display.radix(),
                                                         )
                                                         .unwrap_or(0);
+                                                        edited |= *byte != value;
+                                                        *byte = value;
                                                         self.active_cell_index = None;
                                                     }
                                                 } else if response.gained_focus() {
@@ -319,13 +591,26 @@ impl eframe::App for VoleUI {
                                             }
                                         });
                                 });
+                            // A byte edited by hand no longer comes from its statement
+                            if edited {
+                                self.rom.set_source_map(SourceMap::new());
+                            }
                         }
                         SourceEditMode::Instruction => {
+                            let mut edited = false;
                             egui::ScrollArea::vertical()
                                 .max_height(300.0)
                                 .auto_shrink(false)
                                 .scroll_bar_visibility(ScrollBarVisibility::AlwaysVisible)
                                 .show(ui, |ui| {
+                                    // Words the assembler filled from data directives
+                                    let data_words: Vec<bool> = (0..self.rom.bytes().len())
+                                        .step_by(2)
+                                        .map(|address| {
+                                            self.rom.contains_data(address..address + 2)
+                                        })
+                                        .collect();
+
                                     egui::Grid::new("instruction_grid")
                                         .striped(true)
                                         .num_columns(2)
@@ -344,7 +629,16 @@ impl eframe::App for VoleUI {
                                                     .numeric_display
                                                     .byte_string((i * 2 + 1) as u8);
 
-                                                ui.label(format!("{}-{}", start_byte, end_byte));
+                                                let address =
+                                                    format!("{}-{}", start_byte, end_byte);
+                                                if data_words[i] {
+                                                    ui.label(
+                                                        egui::RichText::new(address).italics(),
+                                                    )
+                                                    .on_hover_text("Data");
+                                                } else {
+                                                    ui.label(address);
+                                                }
 
                                                 let mut byte_string = if self
                                                     .active_cell_index
@@ -406,17 +700,17 @@ impl eframe::App for VoleUI {
                                                         if let Some((lhs, rhs)) =
                                                             opcode.split_at_checked(2)
                                                         {
-                                                            chunk[0] =
+                                                            let word = [
                                                                 u8::from_str_radix(lhs, radix)
-                                                                    .unwrap_or(0);
-
-                                                            chunk[1] =
+                                                                    .unwrap_or(0),
                                                                 u8::from_str_radix(rhs, radix)
-                                                                    .unwrap_or(0);
+                                                                    .unwrap_or(0),
+                                                            ];
+                                                            edited |= chunk != word;
+                                                            chunk.copy_from_slice(&word);
                                                         }
 
                                                         self.active_cell_index = None;
-                                                        //println!("0 {:#X}, 1 {:#X}", chunk[0], chunk[1]);
                                                     }
                                                 } else if response.gained_focus() {
                                                     self.active_cell_index = Some(i);
@@ -427,8 +721,13 @@ impl eframe::App for VoleUI {
                                             }
                                         });
                                 });
+                            // An edited word no longer matches the statement it was compiled from
+                            if edited {
+                                self.rom.set_source_map(SourceMap::new());
+                            }
                         }
                         SourceEditMode::Assembly => {
+                            let highlighted_line = self.highlighted_line();
                             egui::ScrollArea::both().max_height(400.0).show(ui, |ui| {
                                 CodeEditor::default()
                                     .id_source("code editor")
@@ -437,52 +736,73 @@ impl eframe::App for VoleUI {
                                     .with_theme(ColorTheme::AYU_DARK)
                                     .with_syntax(Syntax::vole())
                                     .with_numlines(true)
+                                    .with_highlighted_line(highlighted_line)
                                     .show(ui, &mut self.source_code);
                             });
 
                             if ui.button("Compile").clicked() {
-                                // TODO: UI for errors
                                 let result = self.assembler.assemble(self.source_code.clone());
-                                let (rom, pc) = match result {
+                                let (rom, pc, data, source_map) = match result {
                                     Ok(r) => {
-                                        self.compilation_error = None;
-                                        (r.rom().to_vec(), r.program_counter())
+                                        self.compilation_diagnostics = r.diagnostics().clone();
+                                        self.compiled_listing =
+                                            Listing::new(&self.source_code, &r).to_string();
+                                        (
+                                            r.rom().to_vec(),
+                                            r.program_counter(),
+                                            r.data().to_vec(),
+                                            r.source_map().clone(),
+                                        )
                                     }
-                                    Err(e) => {
-                                        // TODO: Push to UI
-                                        self.compilation_error = Some(e);
-                                        (vec![0; 1], 0)
+                                    Err(diagnostics) => {
+                                        self.compilation_diagnostics = diagnostics;
+                                        self.compiled_listing.clear();
+                                        (vec![0; 1], 0, Vec::new(), SourceMap::new())
                                     }
                                 };
 
                                 self.rom.set_bytes(&rom);
-                                self.compiled_source = rom;
+                                self.rom.set_data(&data);
+                                self.rom.set_source_map(source_map);
+                                self.compiled_code.clone_from(&self.source_code);
                                 self.program_counter = pc;
                             }
 
-                            if self.compilation_error.is_some() {
-                                let error = format!("{}", self.compilation_error.as_ref().unwrap());
-                                ui.label(error);
+                            for diagnostic in &self.compilation_diagnostics {
+                                let color = match diagnostic.severity() {
+                                    Severity::Error => ui.visuals().error_fg_color,
+                                    Severity::Warning => ui.visuals().warn_fg_color,
+                                    Severity::Note => ui.visuals().weak_text_color(),
+                                };
+                                let text = format!("Line {}: {diagnostic}", diagnostic.line());
+                                ui.label(egui::RichText::new(text).color(color));
                             }
 
                             ui.collapsing("Compiled Source", |ui| {
-                                egui::ScrollArea::vertical().show(ui, |ui| {
-                                    ui.label("[");
-                                    for byte in self.compiled_source.iter() {
-                                        let number = self.numeric_display.byte_string(*byte);
-                                        let display_string = format!("  {},", number);
-                                        ui.label(display_string);
-                                    }
-                                    ui.label("]");
+                                if ui.button("Copy Listing").clicked() {
+                                    ui.ctx().copy_text(self.compiled_listing.clone());
+                                }
+                                egui::ScrollArea::both().show(ui, |ui| {
+                                    ui.monospace(&self.compiled_listing);
                                 });
                             });
                         }
                     }
                     ui.separator();
 
-                    if ui.button("Export").clicked() {
-                        self.show_export = true;
-                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Export").clicked() {
+                            self.show_export = true;
+                        }
+
+                        if ui
+                            .button("Open / Save")
+                            .on_hover_text("Load or store the program as a file")
+                            .clicked()
+                        {
+                            self.show_rom_file = true;
+                        }
+                    });
 
                     ui.separator();
 
@@ -497,6 +817,11 @@ impl eframe::App for VoleUI {
                     )
                     .labelled_by(label.id);
 
+                    ui.checkbox(&mut self.wrap_program_counter, "Wrap PC")
+                        .on_hover_text(
+                            "Continue from 0x00 after the end of memory, otherwise stop with an error",
+                        );
+
                     ui.separator();
 
                     let mode_box_response = egui::ComboBox::from_label("Execution Mode")
@@ -517,9 +842,7 @@ impl eframe::App for VoleUI {
                                 .on_hover_text("The CPU cycles around 60 times per second.")
                                 .clicked()
                             {
-                                self.vole.load_rom(self.rom.bytes());
-                                self.vole
-                                    .start(&StartMode::Reset, Some(self.program_counter));
+                                self.start_program();
                                 self.execution_mode = CycleExecutionMode::FullSpeed;
                             }
                         }
@@ -529,9 +852,7 @@ impl eframe::App for VoleUI {
                                 .on_hover_text("Executes the program at the execution speed.")
                                 .clicked()
                             {
-                                self.vole.load_rom(self.rom.bytes());
-                                self.vole
-                                    .start(&StartMode::Reset, Some(self.program_counter));
+                                self.start_program();
                             }
                             let mut speed_limit = limit;
                             ui.add(
@@ -548,21 +869,139 @@ impl eframe::App for VoleUI {
                                 .on_hover_text("Each cycle needs to be manually advanced.")
                                 .clicked()
                             {
-                                self.vole.load_rom(self.rom.bytes());
-                                self.vole
-                                    .start(&StartMode::Reset, Some(self.program_counter));
+                                self.start_program();
                                 self.execution_mode = CycleExecutionMode::Manual(false);
                             }
 
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .button("Previous Cycle")
+                                    .on_hover_text("Undo the last cycle")
+                                    .clicked()
+                                {
+                                    self.vole.step_back();
+                                }
+
+                                if ui
+                                    .button("Next Cycle")
+                                    .on_hover_text("Execute Next Cycle")
+                                    .clicked()
+                                {
+                                    self.paused = false;
+                                    self.execution_mode = CycleExecutionMode::Manual(true);
+                                }
+                            });
+                        }
+                    });
+
+                    if self.paused
+                        && self.vole.running()
+                        && ui
+                            .button("Continue")
+                            .on_hover_text("Resume execution after a breakpoint or watchpoint")
+                            .clicked()
+                    {
+                        self.paused = false;
+                        self.stop_reason = None;
+                    }
+
+                    ui.collapsing("Breakpoints", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut self.breakpoint_address)
+                                    .hexadecimal(2, false, true)
+                                    .prefix("0x"),
+                            );
+                            if ui
+                                .button("Break")
+                                .on_hover_text("Stop when the program counter reaches the address")
+                                .clicked()
+                            {
+                                self.vole.add_breakpoint(self.breakpoint_address);
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut self.watch_address)
+                                    .hexadecimal(2, false, true)
+                                    .prefix("0x"),
+                            );
+                            if ui
+                                .button("Watch Read")
+                                .on_hover_text("Stop after the memory cell is loaded")
+                                .clicked()
+                            {
+                                self.vole
+                                    .add_watchpoint(Watchpoint::MemoryRead(self.watch_address));
+                            }
+                            if ui
+                                .button("Watch Write")
+                                .on_hover_text("Stop after the memory cell is stored")
+                                .clicked()
+                            {
+                                self.vole
+                                    .add_watchpoint(Watchpoint::MemoryWrite(self.watch_address));
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut self.watch_register)
+                                    .range(0..=15)
+                                    .hexadecimal(1, false, true)
+                                    .prefix("r"),
+                            );
                             if ui
-                                .button("Next Cycle")
-                                .on_hover_text("Execute Next Cycle")
+                                .button("Watch Register")
+                                .on_hover_text("Stop after the register value changes")
                                 .clicked()
                             {
-                                self.execution_mode = CycleExecutionMode::Manual(true);
+                                self.vole
+                                    .add_watchpoint(Watchpoint::Register(self.watch_register));
                             }
+                        });
+
+                        ui.separator();
+
+                        let breakpoints: Vec<u8> = self.vole.breakpoints().collect();
+                        for address in breakpoints {
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "Break {}",
+                                    self.numeric_display.byte_string(address)
+                                ));
+                                if ui.small_button("Remove").clicked() {
+                                    self.vole.remove_breakpoint(address);
+                                }
+                            });
+                        }
+
+                        let watchpoints: Vec<Watchpoint> = self.vole.watchpoints().collect();
+                        for watchpoint in watchpoints {
+                            ui.horizontal(|ui| {
+                                ui.label(watchpoint.to_string());
+                                if ui.small_button("Remove").clicked() {
+                                    self.vole.remove_watchpoint(watchpoint);
+                                }
+                            });
                         }
                     });
+
+                    if let Some(range) = self.vole.history_range() {
+                        let mut cycle = self.vole.cycle_count();
+                        let response = ui
+                            .add(egui::Slider::new(&mut cycle, range).text("Timeline"))
+                            .on_hover_text(
+                                "Move the machine backwards and forwards through the executed cycles",
+                            );
+
+                        if response.changed() {
+                            // Pause so the machine stays where it was moved to
+                            self.execution_mode = CycleExecutionMode::Manual(false);
+                            self.timeline_error = self.vole.rewind_to(cycle).err();
+                        }
+                    }
                 });
         });
 
@@ -575,48 +1014,77 @@ impl eframe::App for VoleUI {
                 // Constructing the output string here for copying to the clipboard feature
                 let output_string = match self.source_edit_mode {
                     SourceEditMode::Byte | SourceEditMode::Instruction => {
-                        // TODO: Output types
-                        let mut output: String = "".to_owned();
-                        for (i, chunk) in self.rom.bytes_mut().chunks_mut(2).enumerate() {
-                            if i == 0 {
-                                output.push_str("Address         Contents\n");
-                            }
-
-                            if chunk[0] == 0 && chunk[1] == 0 {
-                                continue;
-                            }
-
-                            let a0 = self.numeric_display.byte_string((i * 2) as u8);
-                            let a1 = self.numeric_display.byte_string((i * 2 + 1) as u8);
-                            let row_string = if self.source_edit_mode == SourceEditMode::Byte {
-                                let b0 = self.numeric_display.byte_string(chunk[0]);
-                                let b1 = self.numeric_display.byte_string(chunk[1]);
-                                let spacing = "            ";
-                                format!("{}{}{}\n{}{}{}\n", a0, spacing, b0, a1, spacing, b1)
-                            } else {
-                                let a = format!("{}-{}", a0, a1);
-                                let b = self
-                                    .numeric_display
-                                    .instruction_string(((chunk[0] as u16) << 8) | chunk[1] as u16);
-                                format!("{}       {}\n", a, b)
-                            };
-                            output.push_str(&row_string);
-                        }
-                        &output.to_owned()
+                        let layout = if self.source_edit_mode == SourceEditMode::Byte {
+                            ContentsLayout::Bytes
+                        } else {
+                            ContentsLayout::Instructions
+                        };
+                        let radix = match self.numeric_display {
+                            NumericDisplay::Hex => ContentsRadix::Hex,
+                            NumericDisplay::Binary => ContentsRadix::Binary,
+                        };
+                        to_address_contents(self.rom.bytes(), layout, radix)
                     }
-                    SourceEditMode::Assembly => &self.source_code,
+                    SourceEditMode::Assembly => self.source_code.clone(),
                 };
 
                 // TODO: Output types
                 ui.label("Under construction");
                 if ui.button("Copy to Clipboard").clicked() {
-                    ctx.copy_text(output_string.to_string());
+                    ctx.copy_text(output_string.clone());
                 }
                 ui.separator();
 
                 ui.label(output_string);
             });
 
+        /*
+            ROM file window
+        */
+        let mut show_rom_file = self.show_rom_file;
+        egui::Window::new("ROM File")
+            .open(&mut show_rom_file)
+            .show(ctx, |ui| {
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    ui.label(
+                        "The format is picked from the extension: .bin (raw binary), \
+                         .hex (Intel HEX), .contents (Address Contents) or .txt (hex dump)",
+                    );
+                    ui.horizontal(|ui| {
+                        let label = ui.label("Path");
+                        ui.text_edit_singleline(&mut self.rom_path)
+                            .labelled_by(label.id);
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Open").clicked() {
+                            self.rom_file_status = Some(self.open_rom_file());
+                        }
+                        if ui.button("Save").clicked() {
+                            self.rom_file_status = Some(self.save_rom_file());
+                        }
+                    });
+
+                    ui.separator();
+                }
+
+                ui.label("Paste Intel HEX, Address Contents or a hex dump to import it");
+                ui.add(
+                    egui::TextEdit::multiline(&mut self.rom_import_text)
+                        .code_editor()
+                        .desired_rows(8),
+                );
+                if ui.button("Import Text").clicked() {
+                    self.rom_file_status = Some(self.import_rom_text());
+                }
+
+                if let Some(status) = &self.rom_file_status {
+                    ui.label(status);
+                }
+            });
+        self.show_rom_file = show_rom_file;
+
         /*
            Visualizer panel
         */
@@ -668,6 +1136,24 @@ impl eframe::App for VoleUI {
                 };
                 ui.label(running);
 
+                if let Some(reason) = &self.stop_reason {
+                    let address = reason.address(self.vole.program_counter());
+                    match self.rom.source_map().span(address.into()) {
+                        Some(span) => {
+                            ui.label(format!("Stopped: {reason} on line {}", span.line()))
+                        }
+                        None => ui.label(format!("Stopped: {reason}")),
+                    };
+                }
+
+                if let Some(error) = &self.load_error {
+                    ui.label(format!("Load failed: {error}"));
+                }
+
+                if let Some(error) = &self.timeline_error {
+                    ui.label(format!("Timeline failed: {error}"));
+                }
+
                 let cycle_text = match self.execution_mode {
                     CycleExecutionMode::FullSpeed => {
                         format!("Next Cycle Time: {:.1}", (1.0 / 60.0))
@@ -723,6 +1209,144 @@ impl eframe::App for VoleUI {
                     });
             });
 
+            /*
+                Display
+            */
+            ui.group(|ui| {
+                ui.horizontal(|ui| {
+                    ui.heading("Display");
+
+                    let mut changed = ui
+                        .checkbox(&mut self.framebuffer_enabled, "Enabled")
+                        .on_hover_text("Map an 8x8 display, each byte is a row of pixels")
+                        .changed();
+
+                    let label = ui.label("Address:");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut self.framebuffer_address)
+                                .range(0..=(u8::MAX - FRAMEBUFFER_SIZE + 1))
+                                .hexadecimal(2, false, true)
+                                .prefix("0x"),
+                        )
+                        .labelled_by(label.id)
+                        .changed();
+
+                    if changed {
+                        self.map_framebuffer();
+                    }
+                });
+
+                if let Some(e) = &self.framebuffer_error {
+                    ui.label(format!("Mapping failed: {e}"));
+                }
+
+                if let Some(framebuffer) = self.vole.bus().device::<Framebuffer>() {
+                    let size = Vec2::splat(PIXEL_SIZE * f32::from(FRAMEBUFFER_SIZE));
+                    let (response, painter) = ui.allocate_painter(size, egui::Sense::hover());
+                    let rect = response.rect;
+                    painter.rect_filled(rect, 0.0, COLOR_PIXEL_OFF);
+
+                    for y in 0..FRAMEBUFFER_SIZE {
+                        for x in 0..FRAMEBUFFER_SIZE {
+                            if framebuffer.pixel(x, y) {
+                                let min =
+                                    rect.min + Vec2::new(f32::from(x), f32::from(y)) * PIXEL_SIZE;
+                                let pixel = egui::Rect::from_min_size(min, Vec2::splat(PIXEL_SIZE));
+                                painter.rect_filled(pixel.shrink(1.0), 0.0, COLOR_PIXEL_ON);
+                            }
+                        }
+                    }
+                }
+            });
+
+            /*
+                Console
+            */
+            ui.group(|ui| {
+                ui.horizontal(|ui| {
+                    ui.heading("Console");
+                    ui.label(format!(
+                        "Data 0x{:02X}, status 0x{:02X}",
+                        CONSOLE_ADDRESS + CONSOLE_DATA,
+                        CONSOLE_ADDRESS + CONSOLE_STATUS
+                    ));
+                    if ui.button("Clear").clicked()
+                        && let Some(console) = self.vole.bus_mut().device_mut::<Console>()
+                    {
+                        console.clear();
+                    }
+                });
+
+                if let Some(console) = self.vole.bus().device::<Console>() {
+                    egui::ScrollArea::vertical()
+                        .id_salt("console_scroll")
+                        .max_height(120.0)
+                        .auto_shrink([false, true])
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            ui.monospace(console.output());
+                        });
+                }
+
+                ui.horizontal(|ui| {
+                    let response = ui.text_edit_singleline(&mut self.input_text).on_hover_text(
+                        format!(
+                            "Typed text is queued for the input port at 0x{:02X}, status 0x{:02X}",
+                            INPUT_ADDRESS + INPUT_DATA,
+                            INPUT_ADDRESS + INPUT_STATUS
+                        ),
+                    );
+                    let entered =
+                        response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                    if (ui.button("Send").clicked() || entered)
+                        && let Some(input) = self.vole.bus_mut().device_mut::<Input>()
+                    {
+                        input.push_str(&self.input_text);
+                        input.push(b'\n');
+                        self.input_text.clear();
+                    }
+
+                    if let Some(input) = self.vole.bus().device::<Input>() {
+                        ui.label(format!("Queued: {}", input.len()));
+                    }
+                });
+            });
+
+            /*
+                Trace
+            */
+            ui.group(|ui| {
+                ui.heading("Trace");
+
+                let mut record = self.vole.trace().is_some();
+                if ui
+                    .checkbox(&mut record, "Record")
+                    .on_hover_text("Record the last instructions executed")
+                    .changed()
+                {
+                    if record {
+                        self.vole.enable_trace(TRACE_CAPACITY);
+                    } else {
+                        self.vole.disable_trace();
+                    }
+                }
+
+                if let Some(trace) = self.vole.trace() {
+                    egui::ScrollArea::vertical()
+                        .id_salt("trace_scroll")
+                        .max_height(120.0)
+                        .auto_shrink([false, true])
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for entry in trace.iter() {
+                                ui.monospace(entry.to_string());
+                            }
+                        });
+                }
+            });
+
             /*
                 Memory
             */
@@ -736,6 +1360,9 @@ impl eframe::App for VoleUI {
                         .min_col_width(4.0)
                         .show(ui, |ui| {
                             let chunk_size = 8;
+                            let mut clicked_line = None;
+                            // The source map only describes the source it was compiled from
+                            let source_current = self.source_code == self.compiled_code;
                             for (i, chunks) in self.vole.memory().chunks(chunk_size).enumerate() {
                                 for (r, chunk) in chunks.iter().enumerate() {
                                     ui.group(|ui| {
@@ -765,19 +1392,36 @@ impl eframe::App for VoleUI {
                                             ui.style().visuals.text_color()
                                         };
 
-                                        // Memory cell text
+                                        // Memory cell text, clicking a compiled byte shows its source
                                         ui.group(|ui| {
                                             let text = egui::RichText::new(
                                                 self.numeric_display.byte_string(*chunk),
                                             )
                                             .color(mem_color);
-                                            ui.label(text).labelled_by(label.id);
+                                            let cell = ui.label(text).labelled_by(label.id);
+
+                                            if source_current
+                                                && let Some(span) =
+                                                    self.rom.source_map().span(index.into())
+                                            {
+                                                let cell = cell
+                                                    .interact(egui::Sense::click())
+                                                    .on_hover_text(format!("Line {}", span.line()));
+                                                if cell.clicked() {
+                                                    clicked_line = Some(span.line() - 1);
+                                                }
+                                            }
                                         });
                                     });
                                 }
 
                                 ui.end_row();
                             }
+
+                            if let Some(line) = clicked_line {
+                                self.selected_line = Some((line, self.vole.program_counter()));
+                                self.source_edit_mode = SourceEditMode::Assembly;
+                            }
                         });
                 });
             });
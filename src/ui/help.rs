@@ -1,4 +1,4 @@
-pub const ASM_SYNTAX: &str = r".org value - Offset the program by value, emulator specific instruction.
+pub const ASM_SYNTAX: &str = r#".org value - Offset the program by value, emulator specific instruction.
 
 ld dest,src - Load a value from src into dest. Src can be a memory address, register, or value. Dest can be a memory address or register.
 
@@ -14,10 +14,20 @@ xor r,s,t - XOR the bit patterns in s and t, stores the result in register r.
 
 rot r,x - Rotates the bit pattern in register r to the right x times.
 
-jp r,<label> - Jump to the label if register r is equal to register 0
+jp r,<label> - Jump to the label or address if register r is equal to register 0
 
 halt - Stop program execution
 
+label: - Names the address of the next instruction, which can be on the same line. Labels starting with a dot are local to the label above them.
+
+.db value, ... - Places bytes, .byte is the same. .fill count, value places count copies of value, .space count places zeros and .ascii "text" places the text.
+
+.equ NAME value - Names a number. .reg name r - Names a register.
+
+.macro name param, ... - Starts a macro which ends at .endm. "name arg, ..." places the lines between with each param replaced by its arg. Local labels in a macro are new on every use.
+
 r0, r1, r2, r3, r4, r5, r6, r7, r8, r9, ra, rb, rc, rd, re, rf - Registers.
 
-Memory addresses are a hex or binary value surrounded by parentheses, values are hex (prefix 0x) or binary (prefix 0b) patterns.";
+Memory addresses are a value surrounded by parentheses, values are hex (prefix 0x), binary (prefix 0b) or decimal numbers.
+
+Values can be expressions using + - * / & | ^ << >>, parentheses, labels, constants, $ for the current address, and low(x) and high(x) for the bytes of a 16-bit value."#;
@@ -1,12 +1,26 @@
+use std::ops::Range;
+
+use crate::asm::source_map::SourceMap;
+
 #[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
 pub struct Rom {
     bytes: Vec<u8>,
+
+    // Address ranges the assembler filled from data directives
+    #[serde(skip)]
+    data: Vec<Range<usize>>,
+
+    // Statements the assembler produced each address from
+    #[serde(skip)]
+    source_map: SourceMap,
 }
 
 impl Rom {
     pub fn new() -> Self {
         Self {
             bytes: vec![0; 256],
+            data: Vec::new(),
+            source_map: SourceMap::new(),
         }
     }
 
@@ -20,5 +34,30 @@ impl Rom {
 
     pub fn set_bytes(&mut self, bytes: &[u8]) {
         self.bytes = bytes.to_vec();
+        self.data.clear();
+        self.source_map = SourceMap::new();
+    }
+
+    pub fn data(&self) -> &[Range<usize>] {
+        &self.data
+    }
+
+    pub fn set_data(&mut self, data: &[Range<usize>]) {
+        self.data = data.to_vec();
+    }
+
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        self.source_map = source_map;
+    }
+
+    /// Returns true if any byte in the range is data
+    pub fn contains_data(&self, addresses: Range<usize>) -> bool {
+        self.data
+            .iter()
+            .any(|range| range.start < addresses.end && addresses.start < range.end)
     }
 }
@@ -1,13 +1,49 @@
-// Occurs in 0x6000
+// Occurs in decode
 #![allow(clippy::cast_possible_truncation)]
 // Occurs in opcode 0x5000
 #![allow(clippy::cast_possible_wrap)]
 // Occurs in opcode 0x5000 and test
 #![allow(clippy::cast_sign_loss)]
 
+/// 8-bit floating point format
+pub mod float;
+
+/// Execution trace
+pub mod trace;
+
+/// Cycle history for reverse stepping
+pub mod history;
+
+/// Breakpoints and watchpoints
+pub mod debug;
+
+/// Instruction set
+pub mod instruction;
+
+/// Memory bus and memory-mapped devices
+pub mod bus;
+
+/// Text console device
+pub mod console;
+
+/// Input port device
+pub mod input;
+
+/// Framebuffer display device
+pub mod framebuffer;
+
+use bus::Bus;
+use debug::{StopReason, Watchpoint};
+use history::{History, HistoryEntry, HistoryError};
+use instruction::Instruction;
+use std::collections::BTreeSet;
+use trace::{MemoryWrite, RegisterWrite, Trace, TraceEntry};
+
 /// Vole virtual machine representation
 pub struct Vole {
-    memory: Vec<u8>,
+    // RAM and the devices mapped over it
+    bus: Bus,
+
     registers: Vec<u8>,
 
     // Program Counter
@@ -17,6 +53,24 @@ pub struct Vole {
     ir: u16,
 
     running: bool,
+
+    // Execution trace, only recorded when enabled
+    trace: Option<Trace>,
+
+    // Cycle history, only recorded when enabled
+    history: Option<History>,
+
+    // Cycles executed since the machine was started
+    cycles: u64,
+
+    // Addresses which stop `run` when the program counter reaches them
+    breakpoints: BTreeSet<u8>,
+
+    // Conditions which stop `run`
+    watchpoints: BTreeSet<Watchpoint>,
+
+    // What happens when the program counter runs past the end of memory
+    pc_policy: ProgramCounterPolicy,
 }
 
 /// Machine start mode
@@ -29,21 +83,119 @@ pub enum StartMode {
     KeepState,
 }
 
+/// How a rom which doesn't fit into memory is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoadPolicy {
+    /// Return `LoadError::TooLarge`
+    #[default]
+    Strict,
+
+    /// Drop the bytes which don't fit
+    Truncate,
+
+    /// Continue loading from address 0x00
+    Wrap,
+}
+
+/// Errors which occur when loading a rom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The rom doesn't fit into memory at the offset
+    TooLarge {
+        /// Rom length
+        size: usize,
+        /// Bytes available from the offset to the end of memory
+        available: usize,
+    },
+
+    /// The offset is past the end of memory
+    OffsetOutOfRange {
+        /// Requested offset
+        offset: usize,
+        /// Memory size
+        memory: usize,
+    },
+
+    /// A wrapped rom is larger than memory and would overwrite itself
+    Overlap {
+        /// Rom length
+        size: usize,
+        /// Memory size
+        memory: usize,
+    },
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::TooLarge { size, available } => write!(
+                f,
+                "The ROM ({size} bytes) is too large to fit into the {available} bytes available"
+            ),
+            LoadError::OffsetOutOfRange { offset, memory } => write!(
+                f,
+                "The offset {offset:#X} is outside of memory ({memory} bytes)"
+            ),
+            LoadError::Overlap { size, memory } => write!(
+                f,
+                "The ROM ({size} bytes) would overwrite itself when wrapped around memory ({memory} bytes)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
 /// Errors which occur when a cycle is performed
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CycleError {
     /// Invalid opcode is found
     InvalidOpcode(String),
+
+    /// The program counter ran past the end of memory at the given address
+    ProgramCounterOverflow(u8),
+}
+
+impl std::fmt::Display for CycleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CycleError::InvalidOpcode(ir) => write!(f, "Invalid instruction {ir}"),
+            CycleError::ProgramCounterOverflow(address) => write!(
+                f,
+                "The program counter ran past the end of memory at {address:#04X}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+/// What happens when the program counter runs past the end of memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgramCounterPolicy {
+    /// Continue from address 0x00, an instruction at 0xFF is fetched from 0xFF and 0x00
+    #[default]
+    Wrap,
+
+    /// Stop the machine with `CycleError::ProgramCounterOverflow` before executing the instruction
+    /// which would carry on past the end, the program counter is left on it
+    Error,
 }
 
 impl Default for Vole {
     fn default() -> Self {
         Self {
             registers: vec![0; 16],
-            memory: vec![0; 256],
+            bus: Bus::new(256),
             pc: 0,
             ir: 0,
             running: false,
+            trace: None,
+            history: None,
+            cycles: 0,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            pc_policy: ProgramCounterPolicy::Wrap,
         }
     }
 }
@@ -58,26 +210,75 @@ impl Vole {
     }
 
     /// Loads the given rom into memory starting at 0
-    pub fn load_rom(&mut self, rom: &[u8]) {
-        // TODO: Fix this
-        if rom.len() <= self.memory.len() {
-            self.memory[0..rom.len()].clone_from_slice(rom);
-        }
+    ///
+    /// # Errors
+    ///
+    /// Will return `LoadError` if the rom doesn't fit into memory.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), LoadError> {
+        self.load_rom_offset(rom, 0)
     }
 
     /// Loads the given rom into memory starting at the given offset
-    pub fn load_rom_offset(&mut self, rom: &[u8], offset: usize) {
-        // TODO: Fix this
-        if rom.len() <= self.memory.len() - offset {
-            self.memory[offset..offset + rom.len()].clone_from_slice(rom);
-        } else {
-            // TODO: UI notification
-            println!(
-                "The ROM {} is too large to fit into memory {} at the offset {:#X}",
-                rom.len(),
-                self.memory.len(),
-                offset,
-            );
+    ///
+    /// # Errors
+    ///
+    /// Will return `LoadError` if the offset is outside of memory or the rom doesn't fit.
+    pub fn load_rom_offset(&mut self, rom: &[u8], offset: usize) -> Result<(), LoadError> {
+        self.load_rom_with(rom, offset, LoadPolicy::Strict)
+            .map(|_| ())
+    }
+
+    /// Loads the given rom into memory starting at the given offset, the policy decides
+    /// what happens to bytes which don't fit.
+    ///
+    /// Returns the number of bytes written. Memory is left unchanged if an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// Will return `LoadError` if the offset is outside of memory or the rom doesn't fit
+    /// under the policy.
+    pub fn load_rom_with(
+        &mut self,
+        rom: &[u8],
+        offset: usize,
+        policy: LoadPolicy,
+    ) -> Result<usize, LoadError> {
+        let cells = self.bus.ram_mut().cells_mut();
+        let memory = cells.len();
+
+        // An empty rom may be loaded at the end of memory
+        if offset > memory || (offset == memory && !rom.is_empty()) {
+            return Err(LoadError::OffsetOutOfRange { offset, memory });
+        }
+
+        let available = memory - offset;
+        if rom.len() <= available {
+            cells[offset..offset + rom.len()].copy_from_slice(rom);
+            return Ok(rom.len());
+        }
+
+        match policy {
+            LoadPolicy::Strict => Err(LoadError::TooLarge {
+                size: rom.len(),
+                available,
+            }),
+            LoadPolicy::Truncate => {
+                cells[offset..].copy_from_slice(&rom[..available]);
+                Ok(available)
+            }
+            LoadPolicy::Wrap => {
+                if rom.len() > memory {
+                    return Err(LoadError::Overlap {
+                        size: rom.len(),
+                        memory,
+                    });
+                }
+
+                let (head, tail) = rom.split_at(available);
+                cells[offset..].copy_from_slice(head);
+                cells[..tail.len()].copy_from_slice(tail);
+                Ok(rom.len())
+            }
         }
     }
 
@@ -85,9 +286,15 @@ impl Vole {
     pub fn start(&mut self, start_mode: &StartMode, start_location: Option<u8>) {
         if *start_mode == StartMode::Reset {
             self.reset_cpu();
+            self.bus.reset_devices();
         }
         self.set_program_counter(start_location.unwrap_or(0x00));
         self.running = true;
+
+        self.cycles = 0;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
     }
 
     /// Is the machine running
@@ -96,20 +303,31 @@ impl Vole {
         self.running
     }
 
-    /// Set the value of a memory address
+    /// Set the value of a RAM cell, devices mapped over the address aren't affected
     pub fn set_memory_value(&mut self, address: u8, value: u8) {
-        self.memory[address as usize] = value;
+        self.bus.ram_mut().cells_mut()[address as usize] = value;
     }
 
-    /// Returns the value of a memory address
+    /// Returns the value of a RAM cell
     pub fn memory_value(&mut self, address: u8) -> u8 {
-        self.memory[address as usize]
+        self.bus.ram().cells()[address as usize]
     }
 
-    /// Returns the memory cells
+    /// Returns the RAM cells
     #[must_use]
     pub fn memory(&self) -> &[u8] {
-        &self.memory
+        self.bus.ram().cells()
+    }
+
+    /// Returns the memory bus
+    #[must_use]
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    /// Returns the memory bus, used to map devices
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
     }
 
     /// Reset the state of the CPU
@@ -146,112 +364,413 @@ impl Vole {
         self.pc = address;
     }
 
+    /// Returns what happens when the program counter runs past the end of memory
+    #[must_use]
+    pub fn program_counter_policy(&self) -> ProgramCounterPolicy {
+        self.pc_policy
+    }
+
+    /// Set what happens when the program counter runs past the end of memory
+    pub fn set_program_counter_policy(&mut self, policy: ProgramCounterPolicy) {
+        self.pc_policy = policy;
+    }
+
     /// Returns the instruction register
     #[must_use]
     pub fn instruction_register(&self) -> u16 {
         self.ir
     }
 
+    /// Start recording an execution trace holding at most `capacity` entries.
+    ///
+    /// Any previously recorded trace is discarded.
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.trace = Some(Trace::new(capacity));
+    }
+
+    /// Stop recording the execution trace and discard it
+    pub fn disable_trace(&mut self) {
+        self.trace = None;
+    }
+
+    /// Returns the execution trace, if enabled
+    #[must_use]
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    /// Returns the execution trace mutable, if enabled
+    pub fn trace_mut(&mut self) -> Option<&mut Trace> {
+        self.trace.as_mut()
+    }
+
+    /// Start recording the cycle history, allowing at most `capacity` cycles to be undone.
+    ///
+    /// Any previously recorded history is discarded.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    /// Stop recording the cycle history and discard it
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Returns the cycle history, if enabled
+    #[must_use]
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Returns the number of cycles executed since the machine was started
+    #[must_use]
+    pub fn cycle_count(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the range of cycles which can be reached with `rewind_to`
+    #[must_use]
+    pub fn history_range(&self) -> Option<std::ops::RangeInclusive<u64>> {
+        self.history.as_ref().map(|history| {
+            let oldest = self.cycles - history.undo_len() as u64;
+            let newest = self.cycles + history.redo_len() as u64;
+            oldest..=newest
+        })
+    }
+
+    /// Undo the most recent cycle.
+    ///
+    /// Returns false if there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let Some(entry) = self.history.as_mut().and_then(History::undo) else {
+            return false;
+        };
+
+        if let Some(write) = entry.trace.register_write {
+            self.registers[write.register as usize] = write.old;
+        }
+
+        if let Some(write) = entry.trace.memory_write {
+            self.restore_memory(write.address, write.old);
+        }
+
+        self.pc = entry.trace.pc;
+        self.ir = entry.ir_before;
+        self.running = entry.running_before;
+        self.cycles -= 1;
+
+        true
+    }
+
+    /// Replay the most recently undone cycle, or perform a new cycle if there is nothing to replay
+    ///
+    /// # Errors
+    ///
+    /// Will return `CycleError` if a new cycle fails.
+    pub fn step_forward(&mut self) -> Result<(), CycleError> {
+        let Some(entry) = self.history.as_mut().and_then(History::redo) else {
+            return self.cycle();
+        };
+
+        if let Some(write) = entry.trace.register_write {
+            self.registers[write.register as usize] = write.new;
+        }
+
+        if let Some(write) = entry.trace.memory_write {
+            self.restore_memory(write.address, write.new);
+        }
+
+        self.pc = entry.pc_after;
+        self.ir = entry.trace.ir;
+        self.running = entry.running_after;
+        self.cycles += 1;
+
+        Ok(())
+    }
+
+    /// Move the machine to the state it had after the given number of cycles
+    ///
+    /// # Errors
+    ///
+    /// Will return `HistoryError` if history isn't enabled or the cycle isn't within `history_range`.
+    pub fn rewind_to(&mut self, cycle: u64) -> Result<(), HistoryError> {
+        let range = self.history_range().ok_or(HistoryError::Disabled)?;
+        if !range.contains(&cycle) {
+            return Err(HistoryError::OutOfRange(cycle));
+        }
+
+        while self.cycles > cycle {
+            self.step_back();
+        }
+
+        while self.cycles < cycle {
+            self.step_forward().map_err(HistoryError::Cycle)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a breakpoint, `run` stops when the program counter reaches the address
+    pub fn add_breakpoint(&mut self, address: u8) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Remove a breakpoint, returns false if there was no breakpoint at the address
+    pub fn remove_breakpoint(&mut self, address: u8) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Returns the breakpoint addresses
+    pub fn breakpoints(&self) -> impl Iterator<Item = u8> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Add a watchpoint, `run` stops after the cycle which triggers it
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.insert(watchpoint);
+    }
+
+    /// Remove a watchpoint, returns false if it wasn't set
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        self.watchpoints.remove(&watchpoint)
+    }
+
+    /// Returns the watchpoints
+    pub fn watchpoints(&self) -> impl Iterator<Item = Watchpoint> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    /// Run at most `max_cycles` cycles, stopping early when the machine halts, fails or
+    /// reaches a breakpoint or watchpoint.
+    ///
+    /// Breakpoints are checked after each cycle so a program can be resumed from the
+    /// breakpoint it stopped at.
+    pub fn run(&mut self, max_cycles: usize) -> StopReason {
+        for _ in 0..max_cycles {
+            if !self.running {
+                return StopReason::Halted;
+            }
+
+            let (entry, result) = self.step();
+            if let Err(e) = result {
+                return StopReason::Error(e);
+            }
+
+            if let Some(watchpoint) = self
+                .watchpoints
+                .iter()
+                .find(|watchpoint| watchpoint.triggered_by(&entry))
+            {
+                return StopReason::Watchpoint(*watchpoint);
+            }
+
+            if !self.running {
+                return StopReason::Halted;
+            }
+
+            if self.breakpoints.contains(&self.pc) {
+                return StopReason::Breakpoint(self.pc);
+            }
+        }
+
+        StopReason::BudgetExhausted
+    }
+
     /// Perform a fetch-decode-execute cycle
     /// # Errors
     ///
     /// Will return `CycleError` if a problem occurs during
     /// a cycle.
     pub fn cycle(&mut self) -> Result<(), CycleError> {
-        // TODO: Execution trace
+        self.step().1
+    }
+
+    /// Perform a cycle, returning what it did alongside the result
+    fn step(&mut self) -> (TraceEntry, Result<(), CycleError>) {
+        let ir_before = self.ir;
+        let running_before = self.running;
+
         /*
            Fetch
         */
-        self.ir = (u16::from(self.memory[self.pc as usize]) << 8)
-            | u16::from(self.memory[(self.pc + 1) as usize]);
+        let pc = self.pc;
+        let low_address = pc.checked_add(1).or(match self.pc_policy {
+            ProgramCounterPolicy::Wrap => Some(0x00),
+            ProgramCounterPolicy::Error => None,
+        });
+
+        let (entry, result) = if let Some(low_address) = low_address {
+            self.ir = u16::from_be_bytes([self.bus.read(pc), self.bus.read(low_address)]);
+
+            /*
+               Decode
+            */
+            let mut entry = TraceEntry::new(pc, self.ir);
+
+            if self.runs_past_end(pc, entry.instruction) {
+                // Stop before executing so resuming doesn't run the instruction twice
+                self.running = false;
+                (entry, Err(CycleError::ProgramCounterOverflow(pc)))
+            } else {
+                // Increment program counter now, the jump instruction will overwrite this during the execute step.
+                // `0xC000` leaves it on the halt, halts with other operands step past themselves and
+                // an invalid instruction stays where it failed
+                if self.ir != 0xC000 && entry.instruction.is_some() {
+                    self.pc = pc.wrapping_add(2);
+                }
 
-        //println!("{:#x}", self.ir);
+                /*
+                   Execute
+                */
+                let result = self.execute(&mut entry);
+                (entry, result)
+            }
+        } else {
+            // The second byte of the instruction is past the end of memory
+            self.running = false;
+            (
+                TraceEntry::new(pc, self.ir),
+                Err(CycleError::ProgramCounterOverflow(pc)),
+            )
+        };
 
-        // TODO: Store PC in execution trace prior to incrementing
-        // Increment program counter now, the jump instruction will overwrite this during the execute step
-        if self.ir != 0xC000 {
-            self.pc += 2;
+        if let Some(trace) = &mut self.trace {
+            trace.push(entry);
         }
 
-        /*
-           Decode
-        */
-        let r = ((self.ir & 0x0F00) >> 8) as u8;
-        let s = ((self.ir & 0x00F0) >> 4) as u8;
-        let t = (self.ir & 0x000F) as u8;
-        let xy = (self.ir & 0x00FF) as u8;
+        if let Some(history) = &mut self.history {
+            history.record(HistoryEntry {
+                trace: entry,
+                ir_before,
+                pc_after: self.pc,
+                running_before,
+                running_after: self.running,
+            });
+        }
+        self.cycles += 1;
 
-        /*
-           Execute
-        */
-        match self.ir & 0xF000 {
-            0x1000 => {
+        (entry, result)
+    }
+
+    /// Returns true if the policy forbids the program counter wrapping after `instruction` at `pc`,
+    /// a taken jump or a `0xC000` halt doesn't move on to the next address
+    fn runs_past_end(&self, pc: u8, instruction: Option<Instruction>) -> bool {
+        if self.pc_policy != ProgramCounterPolicy::Error || pc.checked_add(2).is_some() {
+            return false;
+        }
+
+        match instruction {
+            // Invalid op-codes report themselves
+            None => false,
+            Some(Instruction::Halt) => self.ir != 0xC000,
+            Some(Instruction::Jump { r, .. }) => self.registers[r as usize] != self.registers[0],
+            Some(_) => true,
+        }
+    }
+
+    /// Execute the decoded instruction, recording any writes into the trace entry
+    fn execute(&mut self, entry: &mut TraceEntry) -> Result<(), CycleError> {
+        let Some(instruction) = entry.instruction else {
+            self.running = false;
+            return Err(CycleError::InvalidOpcode(format!("0x{:04X}", self.ir)));
+        };
+
+        match instruction {
+            Instruction::Load { r, address } => {
                 // Load register R with memory XY
-                self.registers[r as usize] = self.memory[xy as usize];
+                entry.memory_read = Some(address);
+                let value = self.bus.read(address);
+                entry.register_write = Some(self.write_register(r, value));
             }
-            0x2000 => {
+            Instruction::LoadImm { r, value } => {
                 // Load register R with XY
-                self.registers[r as usize] = xy;
+                entry.register_write = Some(self.write_register(r, value));
             }
-            0x3000 => {
+            Instruction::Store { r, address } => {
                 // Store register R into memory XY
-                self.memory[xy as usize] = self.registers[r as usize];
+                entry.memory_write = Some(self.write_memory(address, self.registers[r as usize]));
             }
-            0x4000 => {
-                // Move register S into register R
-                self.registers[r as usize] = self.registers[s as usize];
+            Instruction::Move { src, dest } => {
+                // Move register R into register S
+                entry.register_write =
+                    Some(self.write_register(dest, self.registers[src as usize]));
             }
-            0x5000 => {
+            Instruction::AddS { r, s, t } => {
                 // Add register S and register T as twos compliment, store result in R
                 let reg_s = self.registers[s as usize] as i8;
                 let reg_t = self.registers[t as usize] as i8;
-                self.registers[r as usize] = reg_s.wrapping_add(reg_t) as u8;
+                entry.register_write =
+                    Some(self.write_register(r, reg_s.wrapping_add(reg_t) as u8));
             }
-            0x6000 => {
-                // Due to the specification requirements, this machine converts to the lowest precision available and back to u8.
-                // TODO: Implement the FP operation as described in the book
-
+            Instruction::AddF { r, s, t } => {
                 // Add register S and register T as floating point, store result in R
-                self.registers[r as usize] = (f32::from(self.registers[s as usize])
-                    + f32::from(self.registers[t as usize]))
-                    as u8;
+                let value = float::add(self.registers[s as usize], self.registers[t as usize]);
+                entry.register_write = Some(self.write_register(r, value));
             }
-            0x7000 => {
+            Instruction::Or { r, s, t } => {
                 // OR register S and register T, store result in R
-                self.registers[r as usize] =
-                    self.registers[s as usize] | self.registers[t as usize];
+                let value = self.registers[s as usize] | self.registers[t as usize];
+                entry.register_write = Some(self.write_register(r, value));
             }
-            0x8000 => {
+            Instruction::And { r, s, t } => {
                 // AND register S and register T, store result in R
-                self.registers[r as usize] =
-                    self.registers[s as usize] & self.registers[t as usize];
+                let value = self.registers[s as usize] & self.registers[t as usize];
+                entry.register_write = Some(self.write_register(r, value));
             }
-            0x9000 => {
+            Instruction::Xor { r, s, t } => {
                 // XOR register S and register T, store result in R
-                self.registers[r as usize] =
-                    self.registers[s as usize] ^ self.registers[t as usize];
+                let value = self.registers[s as usize] ^ self.registers[t as usize];
+                entry.register_write = Some(self.write_register(r, value));
             }
-            0xA000 => {
-                // Rotate bit pattern in register R to the right X (t) times
-                self.registers[r as usize] = self.registers[r as usize].rotate_right(u32::from(t));
+            Instruction::Rot { r, x } => {
+                // Rotate bit pattern in register R to the right X times
+                let value = self.registers[r as usize].rotate_right(u32::from(x));
+                entry.register_write = Some(self.write_register(r, value));
             }
-            0xB000 => {
+            Instruction::Jump { r, address } => {
                 // Jump to the instruction at memory XY if register R equals register 0
                 if self.registers[r as usize] == self.registers[0] {
-                    self.pc = xy;
+                    self.pc = address;
                 }
             }
-            0xC000 => {
+            Instruction::Halt => {
                 self.running = false;
             }
-            _ => {
-                self.running = false;
-                return Err(CycleError::InvalidOpcode(format!("0x{:04X}", self.ir)));
-            }
         }
 
         Ok(())
     }
+
+    /// Write a register, returning the change for the trace
+    fn write_register(&mut self, register: u8, value: u8) -> RegisterWrite {
+        let old = self.registers[register as usize];
+        self.registers[register as usize] = value;
+        RegisterWrite {
+            register,
+            old,
+            new: value,
+        }
+    }
+
+    /// Write a memory cell, returning the change for the trace
+    fn write_memory(&mut self, address: u8, value: u8) -> MemoryWrite {
+        let old = self.bus.peek(address);
+        self.bus.write(address, value);
+        MemoryWrite {
+            address,
+            old,
+            new: value,
+        }
+    }
+
+    /// Set a memory cell while moving through the history.
+    ///
+    /// Devices can't be rewound, so writes to mapped addresses are skipped.
+    fn restore_memory(&mut self, address: u8, value: u8) {
+        if !self.bus.is_mapped(address) {
+            self.bus.write(address, value);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -279,7 +798,7 @@ mod tests {
 
         // Load the rom into the device
         let mut device = Vole::new();
-        device.load_rom(&rom);
+        device.load_rom(&rom).unwrap();
 
         assert!(rom_length > 0);
         assert_eq!(rom, device.memory()[0..rom_length]);
@@ -298,7 +817,7 @@ mod tests {
 
         // Load the rom into the device
         let mut device = Vole::new();
-        device.load_rom_offset(&rom, start_offset);
+        device.load_rom_offset(&rom, start_offset).unwrap();
 
         assert!(rom_length > 0);
         assert_eq!(
@@ -316,7 +835,7 @@ mod tests {
         let rom = generate_random_rom(rom_length);
 
         let mut device = Vole::new();
-        device.load_rom(&rom);
+        device.load_rom(&rom).unwrap();
 
         // Set random data on the device
         let random_pc = rng.random::<u8>().max(1);
@@ -349,10 +868,467 @@ mod tests {
         let mut device = Vole::new();
 
         let empty_rom = [0x0000];
-        device.load_rom(&empty_rom);
+        device.load_rom(&empty_rom).unwrap();
         device.start(&StartMode::Reset, None);
 
         let result = device.cycle().unwrap_err();
         assert_eq!(result, CycleError::InvalidOpcode("0x0000".to_string()));
+        assert_eq!(result.to_string(), "Invalid instruction 0x0000");
+        assert_eq!(
+            StopReason::Error(result).to_string(),
+            "Error: Invalid instruction 0x0000"
+        );
+    }
+
+    #[test]
+    fn trace() {
+        let mut device = Vole::new();
+
+        // Load 0x2A into r1, store r1 into 0x10, halt
+        device
+            .load_rom(&[0x21, 0x2A, 0x31, 0x10, 0xC0, 0x00])
+            .unwrap();
+        device.enable_trace(2);
+        device.start(&StartMode::Reset, None);
+
+        while device.running() {
+            device.cycle().unwrap();
+        }
+
+        let trace = device.trace().unwrap();
+        assert_eq!(trace.len(), 2);
+
+        let entries: Vec<&TraceEntry> = trace.iter().collect();
+        assert_eq!(entries[0].pc, 0x02);
+        assert_eq!(entries[0].ir, 0x3110);
+        assert_eq!(
+            entries[0].instruction,
+            Some(Instruction::Store {
+                r: 1,
+                address: 0x10
+            })
+        );
+        assert_eq!(entries[0].register_write, None);
+        assert_eq!(
+            entries[0].memory_write,
+            Some(MemoryWrite {
+                address: 0x10,
+                old: 0x00,
+                new: 0x2A
+            })
+        );
+
+        assert_eq!(entries[1].pc, 0x04);
+        assert_eq!(entries[1].ir, 0xC000);
+        assert_eq!(entries[1].memory_write, None);
+    }
+
+    #[test]
+    fn trace_disabled() {
+        let mut device = Vole::new();
+        device.load_rom(&[0x21, 0x2A, 0xC0, 0x00]).unwrap();
+        device.start(&StartMode::Reset, None);
+        device.cycle().unwrap();
+
+        assert!(device.trace().is_none());
+    }
+
+    #[test]
+    fn step_back_and_forward() {
+        let mut device = Vole::new();
+
+        // Load 0x2A into r1, store r1 into 0x10, load 0x01 into r1, halt
+        device
+            .load_rom(&[0x21, 0x2A, 0x31, 0x10, 0x21, 0x01, 0xC0, 0x00])
+            .unwrap();
+        device.enable_history(16);
+        device.start(&StartMode::Reset, None);
+
+        let mut states = vec![(device.registers().to_vec(), device.memory().to_vec())];
+        while device.running() {
+            device.cycle().unwrap();
+            states.push((device.registers().to_vec(), device.memory().to_vec()));
+        }
+        assert_eq!(device.cycle_count(), 4);
+        assert_eq!(device.history_range(), Some(0..=4));
+
+        // Walk all the way back, checking each state
+        for cycle in (0..4).rev() {
+            assert!(device.step_back());
+            assert_eq!(device.cycle_count(), cycle);
+            assert_eq!(device.registers(), states[cycle as usize].0);
+            assert_eq!(device.memory(), states[cycle as usize].1);
+            assert!(device.running());
+        }
+        assert!(!device.step_back());
+        assert_eq!(device.program_counter(), 0x00);
+
+        // Replay to the end
+        device.rewind_to(4).unwrap();
+        assert_eq!(device.registers(), states[4].0);
+        assert_eq!(device.memory(), states[4].1);
+        assert!(!device.running());
+
+        device.rewind_to(2).unwrap();
+        assert_eq!(device.program_counter(), 0x04);
+        assert_eq!(device.registers()[1], 0x2A);
+        assert_eq!(device.memory()[0x10], 0x2A);
+
+        assert_eq!(device.rewind_to(5), Err(HistoryError::OutOfRange(5)));
+    }
+
+    #[test]
+    fn history_capacity() {
+        let mut device = Vole::new();
+
+        // Load 0x01 into r1 forever
+        device.load_rom(&[0x21, 0x01, 0xB0, 0x00]).unwrap();
+        device.enable_history(4);
+        device.start(&StartMode::Reset, None);
+
+        for _ in 0..10 {
+            device.cycle().unwrap();
+        }
+
+        assert_eq!(device.history_range(), Some(6..=10));
+        assert_eq!(device.rewind_to(5), Err(HistoryError::OutOfRange(5)));
+
+        // A new cycle discards the undone cycles
+        device.rewind_to(8).unwrap();
+        device.cycle().unwrap();
+        assert_eq!(device.history_range(), Some(6..=9));
+    }
+
+    #[test]
+    fn run_breakpoint() {
+        let mut device = Vole::new();
+
+        // Load 0x01 into r1, load 0x02 into r2, halt
+        device
+            .load_rom(&[0x21, 0x01, 0x22, 0x02, 0xC0, 0x00])
+            .unwrap();
+        device.add_breakpoint(0x02);
+        device.start(&StartMode::Reset, None);
+
+        let reason = device.run(100);
+        assert_eq!(reason, StopReason::Breakpoint(0x02));
+        assert_eq!(reason.address(device.program_counter()), 0x02);
+        assert_eq!(device.registers()[1], 0x01);
+        assert_eq!(device.registers()[2], 0x00);
+
+        // Resuming from the breakpoint continues the program
+        assert_eq!(device.run(100), StopReason::Halted);
+        assert_eq!(device.registers()[2], 0x02);
+        assert_eq!(device.run(100), StopReason::Halted);
+    }
+
+    #[test]
+    fn run_watchpoints() {
+        let mut device = Vole::new();
+
+        // Load 0x01 into r1, load 0x01 into r1, load mem 0x10 into r2, store r1 into mem 0x10, halt
+        let rom = [0x21, 0x01, 0x21, 0x01, 0x12, 0x10, 0x31, 0x10, 0xC0, 0x00];
+        device.load_rom(&rom).unwrap();
+
+        device.add_watchpoint(Watchpoint::Register(1));
+        device.add_watchpoint(Watchpoint::MemoryRead(0x10));
+        device.add_watchpoint(Watchpoint::MemoryWrite(0x10));
+        device.start(&StartMode::Reset, None);
+
+        assert_eq!(
+            device.run(100),
+            StopReason::Watchpoint(Watchpoint::Register(1))
+        );
+        assert_eq!(device.program_counter(), 0x02);
+
+        // Loading the same value doesn't change the register
+        let reason = device.run(100);
+        assert_eq!(reason, StopReason::Watchpoint(Watchpoint::MemoryRead(0x10)));
+        assert_eq!(device.program_counter(), 0x06);
+        assert_eq!(reason.address(device.program_counter()), 0x04);
+
+        assert_eq!(
+            device.run(100),
+            StopReason::Watchpoint(Watchpoint::MemoryWrite(0x10))
+        );
+        assert_eq!(device.memory()[0x10], 0x01);
+
+        assert!(device.remove_watchpoint(Watchpoint::Register(1)));
+        assert!(!device.remove_watchpoint(Watchpoint::Register(1)));
+        assert_eq!(device.run(100), StopReason::Halted);
+    }
+
+    #[test]
+    fn run_budget_and_errors() {
+        let mut device = Vole::new();
+
+        // Jump to 0x00 forever
+        device.load_rom(&[0xB0, 0x00]).unwrap();
+        device.start(&StartMode::Reset, None);
+        assert_eq!(device.run(10), StopReason::BudgetExhausted);
+        assert_eq!(device.cycle_count(), 10);
+
+        device.load_rom(&[0x00, 0x00]).unwrap();
+        device.start(&StartMode::Reset, None);
+        assert_eq!(
+            device.run(10),
+            StopReason::Error(CycleError::InvalidOpcode("0x0000".to_string()))
+        );
+    }
+
+    #[test]
+    fn move_register() {
+        let mut device = Vole::new();
+
+        // Load 0x2A into ra, move ra into r4, halt
+        device
+            .load_rom(&[0x2A, 0x2A, 0x40, 0xA4, 0xC0, 0x00])
+            .unwrap();
+        device.start(&StartMode::Reset, None);
+        assert_eq!(device.run(10), StopReason::Halted);
+
+        assert_eq!(device.registers()[0x4], 0x2A);
+        assert_eq!(device.registers()[0x0], 0x00);
+    }
+
+    #[test]
+    fn load_rom_errors() {
+        let mut device = Vole::new();
+
+        assert_eq!(
+            device.load_rom(&[0xFF; 257]),
+            Err(LoadError::TooLarge {
+                size: 257,
+                available: 256
+            })
+        );
+        assert_eq!(
+            device.load_rom_offset(&[0xFF; 2], 255),
+            Err(LoadError::TooLarge {
+                size: 2,
+                available: 1
+            })
+        );
+        assert_eq!(
+            device.load_rom_offset(&[0xFF], 256),
+            Err(LoadError::OffsetOutOfRange {
+                offset: 256,
+                memory: 256
+            })
+        );
+        assert_eq!(
+            device.load_rom_offset(&[0xFF], 1000),
+            Err(LoadError::OffsetOutOfRange {
+                offset: 1000,
+                memory: 256
+            })
+        );
+        assert_eq!(device.load_rom_offset(&[], 256), Ok(()));
+
+        // Nothing was written
+        assert!(device.memory().iter().all(|cell| *cell == 0));
+    }
+
+    #[test]
+    fn load_rom_policies() {
+        let mut device = Vole::new();
+
+        assert_eq!(
+            device.load_rom_with(&[0x01, 0x02, 0x03], 0xFE, LoadPolicy::Truncate),
+            Ok(2)
+        );
+        assert_eq!(device.memory()[0xFE..], [0x01, 0x02]);
+        assert_eq!(device.memory()[0x00], 0x00);
+
+        assert_eq!(
+            device.load_rom_with(&[0x04, 0x05, 0x06], 0xFE, LoadPolicy::Wrap),
+            Ok(3)
+        );
+        assert_eq!(device.memory()[0xFE..], [0x04, 0x05]);
+        assert_eq!(device.memory()[0x00], 0x06);
+
+        assert_eq!(
+            device.load_rom_with(&[0xFF; 257], 0x10, LoadPolicy::Wrap),
+            Err(LoadError::Overlap {
+                size: 257,
+                memory: 256
+            })
+        );
+        assert_eq!(
+            device.load_rom_with(&[0xFF; 257], 0x00, LoadPolicy::Truncate),
+            Ok(256)
+        );
+    }
+
+    #[test]
+    fn halt_program_counter() {
+        let mut device = Vole::new();
+
+        // 0xC000 stays on the halt
+        device.load_rom(&[0x21, 0x01, 0xC0, 0x00]).unwrap();
+        device.start(&StartMode::Reset, None);
+        assert_eq!(device.run(10), StopReason::Halted);
+        assert_eq!(device.program_counter(), 0x02);
+
+        // Any other 0xCxxx halts too, past the instruction
+        device.load_rom(&[0x21, 0x01, 0xC1, 0x23]).unwrap();
+        device.start(&StartMode::Reset, None);
+        assert_eq!(device.run(10), StopReason::Halted);
+        assert_eq!(device.program_counter(), 0x04);
+    }
+
+    #[test]
+    fn program_counter_wrap() {
+        let mut device = Vole::new();
+
+        // Load 0x01 into r1 at 0xFE, then continue from 0x00 and halt
+        device.load_rom(&[0xC0, 0x00]).unwrap();
+        device.load_rom_offset(&[0x21, 0x01], 0xFE).unwrap();
+
+        device.start(&StartMode::Reset, Some(0xFE));
+        assert_eq!(device.cycle(), Ok(()));
+        assert_eq!(device.registers()[1], 0x01);
+        assert_eq!(device.program_counter(), 0x00);
+        assert_eq!(device.run(10), StopReason::Halted);
+
+        // Load 0x02 into r2, the instruction is split across 0xFF and 0x00
+        device.load_rom(&[0x02, 0xC0, 0x00]).unwrap();
+        device.set_memory_value(0xFF, 0x22);
+
+        device.start(&StartMode::Reset, Some(0xFF));
+        assert_eq!(device.run(10), StopReason::Halted);
+        assert_eq!(device.registers()[2], 0x02);
+        assert_eq!(device.program_counter(), 0x01);
+    }
+
+    #[test]
+    fn program_counter_overflow_error() {
+        let mut device = Vole::new();
+        device.set_program_counter_policy(ProgramCounterPolicy::Error);
+
+        // Running off the end stops before the last instruction is executed
+        device.load_rom_offset(&[0x21, 0x01], 0xFE).unwrap();
+        device.start(&StartMode::Reset, Some(0xFE));
+        assert_eq!(
+            device.cycle(),
+            Err(CycleError::ProgramCounterOverflow(0xFE))
+        );
+        assert_eq!(device.registers()[1], 0x00);
+        assert_eq!(device.program_counter(), 0xFE);
+        assert!(!device.running());
+
+        // Fetching an instruction from 0xFF is recorded like any other cycle
+        device.enable_trace(10);
+        device.enable_history(10);
+        device.start(&StartMode::Reset, Some(0xFF));
+        assert_eq!(
+            device.run(10),
+            StopReason::Error(CycleError::ProgramCounterOverflow(0xFF))
+        );
+        assert_eq!(device.registers()[1], 0x00);
+        assert!(!device.running());
+        assert_eq!(device.cycle_count(), 1);
+        assert_eq!(device.trace().unwrap().last().unwrap().pc, 0xFF);
+        assert!(device.step_back());
+        assert_eq!(device.program_counter(), 0xFF);
+        assert!(device.running());
+
+        // A jump taken from the end of memory is fine
+        device.load_rom_offset(&[0xB0, 0x00], 0xFE).unwrap();
+        device.load_rom(&[0xC0, 0x00]).unwrap();
+        device.start(&StartMode::Reset, Some(0xFE));
+        assert_eq!(device.run(10), StopReason::Halted);
+
+        // So is `0xC000`, other halts would step past the end
+        device.load_rom_offset(&[0xC0, 0x00], 0xFE).unwrap();
+        device.start(&StartMode::Reset, Some(0xFE));
+        assert_eq!(device.run(10), StopReason::Halted);
+        assert_eq!(device.program_counter(), 0xFE);
+
+        device.load_rom_offset(&[0xC1, 0x23], 0xFE).unwrap();
+        device.start(&StartMode::Reset, Some(0xFE));
+        assert_eq!(
+            device.run(10),
+            StopReason::Error(CycleError::ProgramCounterOverflow(0xFE))
+        );
+        assert_eq!(device.program_counter(), 0xFE);
+
+        // An invalid instruction is reported as itself, without moving the program counter
+        device.load_rom_offset(&[0xD0, 0x12], 0xFE).unwrap();
+        device.start(&StartMode::Reset, Some(0xFE));
+        assert_eq!(
+            device.run(10),
+            StopReason::Error(CycleError::InvalidOpcode("0xD012".to_string()))
+        );
+        assert_eq!(device.program_counter(), 0xFE);
+    }
+
+    #[test]
+    fn program_counter_overflow_resume() {
+        let mut device = Vole::new();
+        device.set_program_counter_policy(ProgramCounterPolicy::Error);
+
+        // Add r2 to r1 at 0xFE, then halt at 0x00
+        device.load_rom(&[0xC0, 0x00]).unwrap();
+        device.load_rom_offset(&[0x51, 0x12], 0xFE).unwrap();
+        device.start(&StartMode::Reset, Some(0xFE));
+        device.set_register_value(2, 0x01);
+        assert_eq!(
+            device.run(10),
+            StopReason::Error(CycleError::ProgramCounterOverflow(0xFE))
+        );
+
+        // Resuming with wrapping allowed runs the addition once
+        device.set_program_counter_policy(ProgramCounterPolicy::Wrap);
+        device.start(&StartMode::KeepState, Some(device.program_counter()));
+        assert_eq!(device.run(10), StopReason::Halted);
+        assert_eq!(device.registers()[1], 0x01);
+        assert_eq!(device.program_counter(), 0x00);
+    }
+
+    #[test]
+    fn mapped_device() {
+        /// Returns the last value written plus one
+        struct Increment(u8);
+
+        impl bus::Device for Increment {
+            fn read(&mut self, _offset: u8) -> u8 {
+                self.0.wrapping_add(1)
+            }
+
+            fn peek(&self, _offset: u8) -> u8 {
+                self.0
+            }
+
+            fn write(&mut self, _offset: u8, value: u8) {
+                self.0 = value;
+            }
+
+            fn reset(&mut self) {
+                self.0 = 0;
+            }
+        }
+
+        let mut device = Vole::new();
+        device.enable_history(16);
+        device.bus_mut().map(0xF0..=0xF0, Increment(0)).unwrap();
+
+        // Store 0x41 into the device, load it back incremented, halt
+        let rom = [0x21, 0x41, 0x31, 0xF0, 0x12, 0xF0, 0xC0, 0x00];
+        device.load_rom(&rom).unwrap();
+        device.start(&StartMode::Reset, None);
+        assert_eq!(device.run(10), StopReason::Halted);
+
+        assert_eq!(device.registers()[2], 0x42);
+        assert_eq!(device.bus().peek(0xF0), 0x41);
+
+        // RAM behind the device is untouched, and stepping back doesn't rewind the device
+        assert_eq!(device.memory()[0xF0], 0x00);
+        while device.step_back() {}
+        assert_eq!(device.registers()[2], 0x00);
+        assert_eq!(device.bus().peek(0xF0), 0x41);
+
+        device.start(&StartMode::Reset, None);
+        assert_eq!(device.bus().peek(0xF0), 0x00);
     }
 }
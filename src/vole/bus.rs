@@ -0,0 +1,312 @@
+//! Memory bus which maps address ranges to devices, unmapped addresses go to RAM
+
+use std::{any::Any, fmt, ops::RangeInclusive};
+
+/// A peripheral which handles loads and stores to the addresses it's mapped to
+///
+/// Offsets are relative to the start of the mapped range.
+pub trait Device: Any {
+    /// Value returned to a load instruction, may have side effects such as consuming input
+    fn read(&mut self, offset: u8) -> u8;
+
+    /// Value shown without side effects, used by the debugger and the trace
+    fn peek(&self, offset: u8) -> u8;
+
+    /// Handle a store instruction
+    fn write(&mut self, offset: u8, value: u8);
+
+    /// Return to the power on state, called when the machine is started with `StartMode::Reset`
+    fn reset(&mut self) {}
+}
+
+/// Random access memory, the default device for every address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ram {
+    cells: Vec<u8>,
+}
+
+impl Ram {
+    /// Create zeroed memory with the given number of cells
+    #[must_use]
+    pub fn new(size: usize) -> Self {
+        Self {
+            cells: vec![0; size],
+        }
+    }
+
+    /// Returns the memory cells
+    #[must_use]
+    pub fn cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// Returns the memory cells for writing
+    pub fn cells_mut(&mut self) -> &mut [u8] {
+        &mut self.cells
+    }
+}
+
+impl Device for Ram {
+    fn read(&mut self, offset: u8) -> u8 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u8) -> u8 {
+        self.cells[offset as usize]
+    }
+
+    fn write(&mut self, offset: u8, value: u8) {
+        self.cells[offset as usize] = value;
+    }
+}
+
+/// Errors which occur when mapping a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    /// The range doesn't contain any addresses
+    EmptyRange,
+
+    /// The range overlaps a device mapped at the given range
+    Overlap(RangeInclusive<u8>),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::EmptyRange => write!(f, "The address range is empty"),
+            MapError::Overlap(range) => write!(
+                f,
+                "The address range overlaps the device at 0x{:02X}..=0x{:02X}",
+                range.start(),
+                range.end()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+struct Mapping {
+    range: RangeInclusive<u8>,
+    device: Box<dyn Device>,
+}
+
+/// Routes loads and stores to the device mapped at the address, or to RAM
+pub struct Bus {
+    ram: Ram,
+    mappings: Vec<Mapping>,
+}
+
+impl Bus {
+    /// Create a bus with the given amount of RAM and no devices
+    #[must_use]
+    pub fn new(ram_size: usize) -> Self {
+        Self {
+            ram: Ram::new(ram_size),
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Returns the RAM behind the bus, mapped addresses keep their RAM cells
+    #[must_use]
+    pub fn ram(&self) -> &Ram {
+        &self.ram
+    }
+
+    /// Returns the RAM behind the bus for writing
+    pub fn ram_mut(&mut self) -> &mut Ram {
+        &mut self.ram
+    }
+
+    /// Map a device to an address range
+    ///
+    /// # Errors
+    ///
+    /// Will return `MapError` if the range is empty or overlaps another device.
+    pub fn map(&mut self, range: RangeInclusive<u8>, device: impl Device) -> Result<(), MapError> {
+        if range.is_empty() {
+            return Err(MapError::EmptyRange);
+        }
+
+        if let Some(mapping) = self
+            .mappings
+            .iter()
+            .find(|m| m.range.start() <= range.end() && range.start() <= m.range.end())
+        {
+            return Err(MapError::Overlap(mapping.range.clone()));
+        }
+
+        self.mappings.push(Mapping {
+            range,
+            device: Box::new(device),
+        });
+        Ok(())
+    }
+
+    /// Remove the device mapped at the address, returning it
+    pub fn unmap(&mut self, address: u8) -> Option<Box<dyn Device>> {
+        let index = self
+            .mappings
+            .iter()
+            .position(|m| m.range.contains(&address))?;
+        Some(self.mappings.remove(index).device)
+    }
+
+    /// Returns the address ranges which have a device mapped, in the order they were mapped
+    pub fn mapped_ranges(&self) -> impl Iterator<Item = RangeInclusive<u8>> + '_ {
+        self.mappings.iter().map(|m| m.range.clone())
+    }
+
+    /// Returns true if the address is handled by a device instead of RAM
+    #[must_use]
+    pub fn is_mapped(&self, address: u8) -> bool {
+        self.mappings.iter().any(|m| m.range.contains(&address))
+    }
+
+    /// Returns the first mapped device of the given type
+    #[must_use]
+    pub fn device<T: Device>(&self) -> Option<&T> {
+        self.mappings
+            .iter()
+            .find_map(|m| (m.device.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    /// Returns the first mapped device of the given type for changing
+    pub fn device_mut<T: Device>(&mut self) -> Option<&mut T> {
+        self.mappings
+            .iter_mut()
+            .find_map(|m| (m.device.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    /// Returns the address range the first device of the given type is mapped to
+    #[must_use]
+    pub fn device_range<T: Device>(&self) -> Option<RangeInclusive<u8>> {
+        self.mappings
+            .iter()
+            .find(|m| (m.device.as_ref() as &dyn Any).is::<T>())
+            .map(|m| m.range.clone())
+    }
+
+    /// Load from an address
+    pub fn read(&mut self, address: u8) -> u8 {
+        match self.mapping_mut(address) {
+            Some((offset, device)) => device.read(offset),
+            None => self.ram.read(address),
+        }
+    }
+
+    /// Look at an address without side effects
+    #[must_use]
+    pub fn peek(&self, address: u8) -> u8 {
+        match self.mappings.iter().find(|m| m.range.contains(&address)) {
+            Some(m) => m.device.peek(address - m.range.start()),
+            None => self.ram.peek(address),
+        }
+    }
+
+    /// Store to an address
+    pub fn write(&mut self, address: u8, value: u8) {
+        match self.mapping_mut(address) {
+            Some((offset, device)) => device.write(offset, value),
+            None => self.ram.write(address, value),
+        }
+    }
+
+    /// Reset every mapped device
+    pub fn reset_devices(&mut self) {
+        for mapping in &mut self.mappings {
+            mapping.device.reset();
+        }
+    }
+
+    fn mapping_mut(&mut self, address: u8) -> Option<(u8, &mut dyn Device)> {
+        self.mappings
+            .iter_mut()
+            .find(|m| m.range.contains(&address))
+            .map(|m| (address - m.range.start(), m.device.as_mut()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts reads, stores the last write
+    #[derive(Default)]
+    struct Port {
+        reads: u8,
+        written: Option<(u8, u8)>,
+    }
+
+    impl Device for Port {
+        fn read(&mut self, _offset: u8) -> u8 {
+            self.reads += 1;
+            self.reads
+        }
+
+        fn peek(&self, _offset: u8) -> u8 {
+            self.reads
+        }
+
+        fn write(&mut self, offset: u8, value: u8) {
+            self.written = Some((offset, value));
+        }
+
+        fn reset(&mut self) {
+            *self = Self::default();
+        }
+    }
+
+    #[test]
+    fn ram_by_default() {
+        let mut bus = Bus::new(256);
+        bus.write(0x10, 0x2A);
+        assert_eq!(bus.read(0x10), 0x2A);
+        assert_eq!(bus.peek(0x10), 0x2A);
+        assert_eq!(bus.ram().cells()[0x10], 0x2A);
+        assert!(!bus.is_mapped(0x10));
+    }
+
+    #[test]
+    fn mapped_device() {
+        let mut bus = Bus::new(256);
+        bus.map(0xF0..=0xF1, Port::default()).unwrap();
+
+        assert!(bus.is_mapped(0xF1));
+        assert_eq!(bus.read(0xF0), 1);
+        assert_eq!(bus.read(0xF1), 2);
+        assert_eq!(bus.peek(0xF0), 2);
+
+        // Stores go to the device with an offset, RAM is untouched
+        bus.write(0xF1, 0x41);
+        assert_eq!(bus.device::<Port>().unwrap().written, Some((1, 0x41)));
+        assert_eq!(bus.ram().cells()[0xF1], 0x00);
+        assert_eq!(bus.device_range::<Port>(), Some(0xF0..=0xF1));
+
+        bus.reset_devices();
+        assert_eq!(bus.device::<Port>().unwrap().reads, 0);
+
+        assert!(bus.unmap(0xF0).is_some());
+        assert!(bus.device::<Port>().is_none());
+        assert_eq!(bus.read(0xF0), 0x00);
+    }
+
+    #[test]
+    fn map_errors() {
+        let mut bus = Bus::new(256);
+        bus.map(0x80..=0x8F, Port::default()).unwrap();
+
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 0x10..=0x0F;
+        assert_eq!(bus.map(empty, Port::default()), Err(MapError::EmptyRange));
+        assert_eq!(
+            bus.map(0x8F..=0x90, Port::default()),
+            Err(MapError::Overlap(0x80..=0x8F))
+        );
+        assert_eq!(
+            bus.map(0x00..=0xFF, Port::default()),
+            Err(MapError::Overlap(0x80..=0x8F))
+        );
+        assert!(bus.map(0x90..=0x90, Port::default()).is_ok());
+    }
+}
@@ -0,0 +1,150 @@
+//! Memory-mapped text console, stores to the data address print a character
+
+use super::bus::Device;
+
+/// Default address of the data register, the status register follows it
+pub const CONSOLE_ADDRESS: u8 = 0xF0;
+
+/// Offset of the data register, a store appends the byte to the output
+pub const CONSOLE_DATA: u8 = 0x00;
+
+/// Offset of the status register, a load returns `CONSOLE_READY` when a byte can be written
+pub const CONSOLE_STATUS: u8 = 0x01;
+
+/// Status value when the console accepts output, it always does
+pub const CONSOLE_READY: u8 = 0x01;
+
+/// Number of addresses the console occupies
+pub const CONSOLE_SIZE: u8 = 2;
+
+/// Converts a byte written to the console into text.
+///
+/// - Printable ASCII, newline and tab are kept.
+/// - Carriage return and null are ignored.
+/// - Anything else becomes the replacement character.
+#[must_use]
+pub fn byte_to_char(byte: u8) -> Option<char> {
+    match byte {
+        b'\n' | b'\t' | 0x20..=0x7E => Some(char::from(byte)),
+        0x00 | b'\r' => None,
+        _ => Some(char::REPLACEMENT_CHARACTER),
+    }
+}
+
+/// Text output device
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Console {
+    bytes: Vec<u8>,
+    output: String,
+}
+
+impl Console {
+    /// Create an empty console
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the text written so far
+    #[must_use]
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Returns the bytes written so far, including those which aren't shown
+    #[must_use]
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Remove all output
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.output.clear();
+    }
+}
+
+impl Device for Console {
+    fn read(&mut self, offset: u8) -> u8 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u8) -> u8 {
+        match offset {
+            CONSOLE_STATUS => CONSOLE_READY,
+            _ => 0x00,
+        }
+    }
+
+    fn write(&mut self, offset: u8, value: u8) {
+        if offset != CONSOLE_DATA {
+            return;
+        }
+
+        self.bytes.push(value);
+        if let Some(c) = byte_to_char(value) {
+            self.output.push(c);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asm::assembler::Assembler,
+        vole::{StartMode, Vole, debug::StopReason},
+    };
+
+    #[test]
+    fn byte_mapping() {
+        assert_eq!(byte_to_char(b'A'), Some('A'));
+        assert_eq!(byte_to_char(b'\n'), Some('\n'));
+        assert_eq!(byte_to_char(b'\r'), None);
+        assert_eq!(byte_to_char(0x00), None);
+        assert_eq!(byte_to_char(0x7F), Some(char::REPLACEMENT_CHARACTER));
+        assert_eq!(byte_to_char(0xC9), Some(char::REPLACEMENT_CHARACTER));
+    }
+
+    #[test]
+    fn print_hello() {
+        let source = "
+            ld r1, (0xF1)
+            ld r2, 0x48
+            ld (0xF0), r2
+            ld r2, 0x49
+            ld (0xF0), r2
+            ld r2, 0x0D
+            ld (0xF0), r2
+            ld r2, 0x0A
+            ld (0xF0), r2
+            halt
+        ";
+        let result = Assembler::new().assemble(source.to_string()).unwrap();
+
+        let mut vole = Vole::new();
+        vole.bus_mut()
+            .map(
+                CONSOLE_ADDRESS..=CONSOLE_ADDRESS + CONSOLE_SIZE - 1,
+                Console::new(),
+            )
+            .unwrap();
+        vole.load_rom(result.rom()).unwrap();
+        vole.start(&StartMode::Reset, None);
+
+        assert_eq!(vole.run(100), StopReason::Halted);
+        assert_eq!(vole.registers()[1], CONSOLE_READY);
+
+        let console = vole.bus().device::<Console>().unwrap();
+        assert_eq!(console.output(), "HI\n");
+        assert_eq!(console.bytes(), [0x48, 0x49, 0x0D, 0x0A]);
+
+        // Starting again clears the output
+        vole.start(&StartMode::Reset, None);
+        assert_eq!(vole.bus().device::<Console>().unwrap().output(), "");
+    }
+}
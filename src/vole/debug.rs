@@ -0,0 +1,93 @@
+//! Breakpoints, watchpoints and the reasons `Vole::run` stops
+
+use super::{CycleError, trace::TraceEntry};
+use std::fmt;
+
+/// A condition which stops `Vole::run` after the cycle that triggered it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Watchpoint {
+    /// An instruction loaded the memory cell into a register
+    MemoryRead(u8),
+
+    /// An instruction stored a register into the memory cell
+    MemoryWrite(u8),
+
+    /// An instruction changed the value of the register
+    Register(u8),
+}
+
+impl Watchpoint {
+    /// Returns true if the cycle described by the trace entry triggers the watchpoint
+    #[must_use]
+    pub fn triggered_by(&self, entry: &TraceEntry) -> bool {
+        match *self {
+            Watchpoint::MemoryRead(address) => entry.memory_read == Some(address),
+            Watchpoint::MemoryWrite(address) => entry
+                .memory_write
+                .is_some_and(|write| write.address == address),
+            Watchpoint::Register(register) => entry
+                .register_write
+                .is_some_and(|write| write.register == register && write.old != write.new),
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::MemoryRead(address) => write!(f, "Read (0x{address:02X})"),
+            Watchpoint::MemoryWrite(address) => write!(f, "Write (0x{address:02X})"),
+            Watchpoint::Register(register) => write!(f, "Change r{register:X}"),
+        }
+    }
+}
+
+/// The reason `Vole::run` returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// The machine executed a halt instruction or wasn't running
+    Halted,
+
+    /// The program counter reached a breakpoint address
+    Breakpoint(u8),
+
+    /// A watchpoint was triggered
+    Watchpoint(Watchpoint),
+
+    /// A cycle failed, such as an invalid opcode
+    Error(CycleError),
+
+    /// The cycle budget was used up
+    BudgetExhausted,
+}
+
+impl StopReason {
+    /// Returns the address of the instruction the machine stopped at, from the program counter
+    /// after stopping
+    ///
+    /// A halt, an invalid instruction or an exhausted budget leaves the program counter on the
+    /// instruction, a watchpoint has already moved it on by two.
+    #[must_use]
+    pub fn address(&self, program_counter: u8) -> u8 {
+        match self {
+            StopReason::Breakpoint(address)
+            | StopReason::Error(CycleError::ProgramCounterOverflow(address)) => *address,
+            StopReason::Watchpoint(_) => program_counter.wrapping_sub(2),
+            StopReason::Halted | StopReason::Error(_) | StopReason::BudgetExhausted => {
+                program_counter
+            }
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Halted => write!(f, "Halted"),
+            StopReason::Breakpoint(address) => write!(f, "Breakpoint at 0x{address:02X}"),
+            StopReason::Watchpoint(watchpoint) => write!(f, "Watchpoint {watchpoint}"),
+            StopReason::Error(e) => write!(f, "Error: {e}"),
+            StopReason::BudgetExhausted => write!(f, "Cycle budget exhausted"),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! The 8-bit floating point format described in Section 1.7 and Figure 1.24.
+//!
+//! ```text
+//!  bit:  7   6 5 4   3 2 1 0
+//!        S   E E E   M M M M
+//! ```
+//!
+//! - `S` is the sign bit, 1 means negative.
+//! - `EEE` is the exponent stored in excess-4 notation (0b100 is 2^0).
+//! - `MMMM` is the mantissa, the radix point sits to the left of it.
+//!
+//! The value of a bit pattern is `0.MMMM * 2^(EEE - 4)`. Every value the format
+//! can hold is a whole multiple of 1/256, which the codec calls a unit. Working
+//! in units keeps the arithmetic exact, only encoding loses information.
+
+use std::fmt;
+
+/// Number of units in 1.0
+const UNITS_PER_ONE: f32 = 256.0;
+
+/// Largest mantissa
+const MANTISSA_MAX: u8 = 0x0F;
+
+/// Smallest normalized mantissa, the high-order bit is set
+const MANTISSA_NORMALIZED: u8 = 0x08;
+
+/// Largest exponent field
+const EXPONENT_MAX: u8 = 0x07;
+
+/// Largest magnitude in units, 0.1111 * 2^3 = 7.5
+const MAGNITUDE_MAX: u32 = (MANTISSA_MAX as u32) << EXPONENT_MAX;
+
+/// Errors which occur when encoding a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatError {
+    /// The magnitude is larger than 7.5
+    Overflow,
+
+    /// The value is NaN or infinite
+    NotFinite,
+}
+
+impl fmt::Display for FloatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FloatError::Overflow => {
+                write!(f, "Value is too large for the 8-bit floating point format")
+            }
+            FloatError::NotFinite => write!(f, "Value is not a finite number"),
+        }
+    }
+}
+
+impl std::error::Error for FloatError {}
+
+/// Returns true if the sign bit is set
+#[must_use]
+pub const fn sign(bits: u8) -> bool {
+    bits & 0x80 != 0
+}
+
+/// Returns the raw exponent field, still in excess-4 notation
+#[must_use]
+pub const fn exponent(bits: u8) -> u8 {
+    (bits >> 4) & EXPONENT_MAX
+}
+
+/// Returns the mantissa field
+#[must_use]
+pub const fn mantissa(bits: u8) -> u8 {
+    bits & MANTISSA_MAX
+}
+
+/// Returns true if the bit pattern is normalized.
+///
+/// Zero is considered normalized.
+#[must_use]
+pub const fn is_normalized(bits: u8) -> bool {
+    let m = mantissa(bits);
+    m == 0 || m >= MANTISSA_NORMALIZED
+}
+
+/// Decodes the bit pattern into a signed number of units
+const fn to_units(bits: u8) -> i32 {
+    let magnitude = (mantissa(bits) as i32) << exponent(bits);
+    if sign(bits) { -magnitude } else { magnitude }
+}
+
+/// Encodes a signed number of units, truncating bits that don't fit into the mantissa.
+///
+/// Magnitudes past the largest value are clamped.
+fn from_units(units: i32) -> u8 {
+    let negative = units < 0;
+    let magnitude = units.unsigned_abs().min(MAGNITUDE_MAX);
+
+    if magnitude == 0 {
+        return 0x00;
+    }
+
+    // Shift the mantissa until its high-order bit is the fourth bit, values too small for
+    // that keep the smallest exponent and an unnormalized mantissa.
+    let width = u32::BITS - magnitude.leading_zeros();
+    let exponent = width.saturating_sub(4);
+    let mantissa = magnitude >> exponent;
+
+    let sign = if negative { 0x80 } else { 0x00 };
+    sign | ((exponent as u8) << 4) | mantissa as u8
+}
+
+/// Decodes the bit pattern into a float
+#[must_use]
+pub fn decode(bits: u8) -> f32 {
+    // Every unit count fits exactly into an f32
+    to_units(bits) as f32 / UNITS_PER_ONE
+}
+
+/// Encodes the value, truncating any bits that don't fit into the mantissa.
+///
+/// # Errors
+///
+/// Will return `FloatError` if the value can't be represented.
+pub fn encode(value: f32) -> Result<u8, FloatError> {
+    if !value.is_finite() {
+        return Err(FloatError::NotFinite);
+    }
+
+    let units = (value * UNITS_PER_ONE).trunc();
+    if units.abs() > MAGNITUDE_MAX as f32 {
+        return Err(FloatError::Overflow);
+    }
+
+    Ok(from_units(units as i32))
+}
+
+/// Returns the value which is actually stored when encoding, the difference to the
+/// original value is the truncation error.
+///
+/// # Errors
+///
+/// Will return `FloatError` if the value can't be represented.
+pub fn truncate(value: f32) -> Result<f32, FloatError> {
+    encode(value).map(decode)
+}
+
+/// Rewrites the bit pattern so the high-order bit of the mantissa is set, keeping the value.
+///
+/// Negative zero becomes positive zero.
+#[must_use]
+pub fn normalize(bits: u8) -> u8 {
+    from_units(to_units(bits))
+}
+
+/// Adds two bit patterns, the result is normalized and truncated.
+///
+/// # Errors
+///
+/// Will return `FloatError::Overflow` if the sum is larger than 7.5.
+pub fn checked_add(lhs: u8, rhs: u8) -> Result<u8, FloatError> {
+    let sum = to_units(lhs) + to_units(rhs);
+    if sum.unsigned_abs() > MAGNITUDE_MAX {
+        return Err(FloatError::Overflow);
+    }
+
+    Ok(from_units(sum))
+}
+
+/// Adds two bit patterns, the result is normalized and truncated.
+///
+/// Sums larger than 7.5 are clamped to the largest value with the same sign.
+#[must_use]
+pub fn add(lhs: u8, rhs: u8) -> u8 {
+    from_units(to_units(lhs) + to_units(rhs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference model, picks the best pattern by searching every positive bit pattern
+    /// using f64 arithmetic rather than the unit math.
+    fn reference_encode(value: f64) -> u8 {
+        let magnitude = value.abs();
+
+        let mut best: Option<(f64, u8)> = None;
+        for bits in 0x00..=0x7Fu8 {
+            let m = f64::from(bits & 0x0F) / 16.0;
+            let e = i32::from((bits >> 4) & 0x07) - 4;
+            let candidate = m * 2f64.powi(e);
+
+            if candidate > magnitude {
+                continue;
+            }
+
+            // Prefer the larger value, then the normalized pattern, then the smaller exponent
+            let better = match best {
+                None => true,
+                Some((v, b)) => {
+                    candidate > v
+                        || (candidate == v && bits & 0x08 != 0 && b & 0x08 == 0)
+                        || (candidate == v && (bits & 0x08 != 0) == (b & 0x08 != 0) && bits < b)
+                }
+            };
+
+            if better {
+                best = Some((candidate, bits));
+            }
+        }
+
+        let (v, bits) = best.unwrap_or((0.0, 0x00));
+        if v == 0.0 || value >= 0.0 {
+            bits
+        } else {
+            bits | 0x80
+        }
+    }
+
+    fn reference_decode(bits: u8) -> f64 {
+        let m = f64::from(bits & 0x0F) / 16.0;
+        let e = i32::from((bits >> 4) & 0x07) - 4;
+        let v = m * 2f64.powi(e);
+        if bits & 0x80 != 0 { -v } else { v }
+    }
+
+    #[test]
+    fn decode_book_examples() {
+        // 0 110 1011 = 0.1011 * 2^2 = 2 3/4
+        assert_eq!(decode(0b0110_1011), 2.75);
+
+        // 1 011 1100 = -0.1100 * 2^-1 = -3/8
+        assert_eq!(decode(0b1011_1100), -0.375);
+
+        // 0 100 1000 = 0.1000 * 2^0 = 1/2
+        assert_eq!(decode(0b0100_1000), 0.5);
+
+        assert_eq!(decode(0x00), 0.0);
+        assert_eq!(decode(0x7F), 7.5);
+        assert_eq!(decode(0xFF), -7.5);
+    }
+
+    #[test]
+    fn decode_all() {
+        for bits in 0..=u8::MAX {
+            assert_eq!(f64::from(decode(bits)), reference_decode(bits));
+        }
+    }
+
+    #[test]
+    fn encode_book_examples() {
+        // 1 1/8 = 0.1001 * 2^1
+        assert_eq!(encode(1.125), Ok(0b0101_1001));
+
+        // 2 5/8 = 10.101 needs five mantissa bits, the last 1 is truncated to give 2 1/2
+        assert_eq!(encode(2.625), Ok(0b0110_1010));
+        assert_eq!(truncate(2.625), Ok(2.5));
+
+        assert_eq!(encode(-0.375), Ok(0b1011_1100));
+    }
+
+    #[test]
+    fn encode_errors() {
+        assert_eq!(encode(7.5), Ok(0x7F));
+        assert_eq!(encode(8.0), Err(FloatError::Overflow));
+        assert_eq!(encode(-8.0), Err(FloatError::Overflow));
+        assert_eq!(encode(f32::NAN), Err(FloatError::NotFinite));
+        assert_eq!(encode(f32::INFINITY), Err(FloatError::NotFinite));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for bits in 0..=u8::MAX {
+            let value = decode(bits);
+            let encoded = encode(value).unwrap();
+            assert_eq!(decode(encoded), value);
+            assert_eq!(encoded, normalize(bits));
+            assert!(is_normalized(encoded) || exponent(encoded) == 0);
+        }
+    }
+
+    #[test]
+    fn normalize_patterns() {
+        // 0.0100 * 2^1 is 0.1000 * 2^0
+        assert_eq!(normalize(0b0101_0100), 0b0100_1000);
+
+        // Negative zero
+        assert_eq!(normalize(0x80), 0x00);
+
+        // Too small to normalize
+        assert_eq!(normalize(0x01), 0x01);
+    }
+
+    #[test]
+    fn add_exhaustive() {
+        for lhs in 0..=u8::MAX {
+            for rhs in 0..=u8::MAX {
+                let sum = reference_decode(lhs) + reference_decode(rhs);
+                let expected = reference_encode(sum);
+
+                assert_eq!(add(lhs, rhs), expected, "{lhs:#010b} + {rhs:#010b} = {sum}");
+
+                if sum.abs() > 7.5 {
+                    assert_eq!(checked_add(lhs, rhs), Err(FloatError::Overflow));
+                } else {
+                    assert_eq!(checked_add(lhs, rhs), Ok(expected));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn add_truncation_error() {
+        // 2 1/2 + 1/8 = 2 5/8, which is truncated back to 2 1/2
+        let lhs = encode(2.5).unwrap();
+        let rhs = encode(0.125).unwrap();
+        assert_eq!(decode(add(lhs, rhs)), 2.5);
+
+        // The order of addition matters, 1/8 + 1/8 + 2 1/2 = 2 3/4
+        let small = add(rhs, rhs);
+        assert_eq!(decode(add(small, lhs)), 2.75);
+    }
+}
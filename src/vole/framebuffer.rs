@@ -0,0 +1,108 @@
+//! Memory-mapped monochrome display, each byte is a row of pixels
+
+use super::bus::Device;
+
+/// Default address of the first row
+pub const FRAMEBUFFER_ADDRESS: u8 = 0xE0;
+
+/// Pixels per row and number of rows
+pub const FRAMEBUFFER_SIZE: u8 = 8;
+
+/// 8x8 monochrome display, the most significant bit of a row is the leftmost pixel
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Framebuffer {
+    rows: [u8; FRAMEBUFFER_SIZE as usize],
+}
+
+impl Framebuffer {
+    /// Create a display with every pixel off
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the rows, top first
+    #[must_use]
+    pub fn rows(&self) -> &[u8] {
+        &self.rows
+    }
+
+    /// Returns true if the pixel is on, (0, 0) is the top left. Pixels off the display are off
+    #[must_use]
+    pub fn pixel(&self, x: u8, y: u8) -> bool {
+        let row = self.peek(y);
+        0x80_u8
+            .checked_shr(u32::from(x))
+            .is_some_and(|mask| row & mask != 0)
+    }
+}
+
+impl Device for Framebuffer {
+    fn read(&mut self, offset: u8) -> u8 {
+        self.peek(offset)
+    }
+
+    /// Reads past the last row return 0
+    fn peek(&self, offset: u8) -> u8 {
+        self.rows.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    /// Writes past the last row are ignored
+    fn write(&mut self, offset: u8, value: u8) {
+        if let Some(row) = self.rows.get_mut(offset as usize) {
+            *row = value;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.rows = [0; FRAMEBUFFER_SIZE as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vole::{StartMode, Vole, debug::StopReason};
+
+    #[test]
+    fn pixels() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.write(0, 0b1000_0001);
+        framebuffer.write(7, 0b0001_0000);
+
+        assert!(framebuffer.pixel(0, 0));
+        assert!(framebuffer.pixel(7, 0));
+        assert!(!framebuffer.pixel(1, 0));
+        assert!(framebuffer.pixel(3, 7));
+        assert_eq!(framebuffer.read(7), 0b0001_0000);
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.write(0, 0xFF);
+        framebuffer.write(8, 0xFF);
+        framebuffer.write(0xFF, 0xFF);
+
+        assert_eq!(framebuffer.rows(), [0xFF, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(framebuffer.peek(8), 0);
+        assert!(!framebuffer.pixel(8, 0));
+        assert!(!framebuffer.pixel(0, 8));
+    }
+
+    #[test]
+    fn rotate_onto_display() {
+        let mut vole = Vole::new();
+        vole.bus_mut().map(0x80..=0x87, Framebuffer::new()).unwrap();
+
+        // Store a pixel in the first row, rotate it right once and store in the second row
+        let rom = [0x21, 0x80, 0x31, 0x80, 0xA1, 0x01, 0x31, 0x81, 0xC0, 0x00];
+        vole.load_rom(&rom).unwrap();
+        vole.start(&StartMode::Reset, None);
+        assert_eq!(vole.run(10), StopReason::Halted);
+
+        let framebuffer = vole.bus().device::<Framebuffer>().unwrap();
+        assert_eq!(framebuffer.rows(), [0x80, 0x40, 0, 0, 0, 0, 0, 0]);
+        assert!(framebuffer.pixel(1, 1));
+    }
+}
@@ -0,0 +1,124 @@
+//! Cycle history used for stepping backwards and forwards through execution
+
+use super::{CycleError, trace::TraceEntry};
+use std::{collections::VecDeque, fmt};
+
+/// The change a single cycle made to the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Fetched instruction and the writes it performed
+    pub trace: TraceEntry,
+
+    /// Instruction register before the cycle
+    pub ir_before: u16,
+
+    /// Program counter after the cycle
+    pub pc_after: u8,
+
+    /// Running state before the cycle
+    pub running_before: bool,
+
+    /// Running state after the cycle
+    pub running_after: bool,
+}
+
+/// Bounded history of cycles which can be undone and redone
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    // Cycles which can be undone, oldest first
+    undo: VecDeque<HistoryEntry>,
+
+    // Cycles which were undone and can be replayed, most recently undone last
+    redo: Vec<HistoryEntry>,
+
+    capacity: usize,
+}
+
+/// Errors which occur when moving through the history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryError {
+    /// History isn't being recorded
+    Disabled,
+
+    /// The requested cycle is no longer, or not yet, in the history
+    OutOfRange(u64),
+
+    /// A cycle failed while moving forwards
+    Cycle(CycleError),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::Disabled => write!(f, "History is not being recorded"),
+            HistoryError::OutOfRange(cycle) => write!(f, "Cycle {cycle} is not in the history"),
+            HistoryError::Cycle(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl History {
+    /// Create a history holding at most `capacity` cycles
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            undo: VecDeque::with_capacity(capacity),
+            redo: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Returns the maximum number of cycles which can be undone
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of cycles which can be undone
+    #[must_use]
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Returns the number of cycles which can be replayed
+    #[must_use]
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Record a newly executed cycle, this discards anything which could be replayed
+    pub(crate) fn record(&mut self, entry: HistoryEntry) {
+        self.redo.clear();
+
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(entry);
+    }
+
+    /// Take the most recent cycle for undoing
+    pub(crate) fn undo(&mut self) -> Option<HistoryEntry> {
+        let entry = self.undo.pop_back()?;
+        self.redo.push(entry);
+        Some(entry)
+    }
+
+    /// Take the most recently undone cycle for replaying
+    pub(crate) fn redo(&mut self) -> Option<HistoryEntry> {
+        let entry = self.redo.pop()?;
+        self.undo.push_back(entry);
+        Some(entry)
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
@@ -0,0 +1,176 @@
+//! Memory-mapped input port, loads from the data address take the next byte from a queue
+
+use super::bus::Device;
+use std::collections::VecDeque;
+
+/// Default address of the data register, the status register follows it
+pub const INPUT_ADDRESS: u8 = 0xF2;
+
+/// Offset of the data register, a load takes the next byte or returns 0x00 if there is none
+pub const INPUT_DATA: u8 = 0x00;
+
+/// Offset of the status register, a load returns `INPUT_READY` when a byte is waiting
+pub const INPUT_STATUS: u8 = 0x01;
+
+/// Status value when a byte is waiting, otherwise the status is 0x00
+pub const INPUT_READY: u8 = 0x01;
+
+/// Number of addresses the input port occupies
+pub const INPUT_SIZE: u8 = 2;
+
+/// Input device fed from a queue of bytes
+///
+/// Queued input is kept when the machine is reset, so it can be filled before a program starts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Input {
+    queue: VecDeque<u8>,
+}
+
+impl Input {
+    /// Create an input port with nothing queued
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an input port with the bytes queued
+    #[must_use]
+    pub fn with_bytes(bytes: &[u8]) -> Self {
+        Self {
+            queue: bytes.iter().copied().collect(),
+        }
+    }
+
+    /// Queue a byte
+    pub fn push(&mut self, byte: u8) {
+        self.queue.push_back(byte);
+    }
+
+    /// Queue the bytes in order
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        self.queue.extend(bytes);
+    }
+
+    /// Queue typed text, characters outside of ASCII are skipped
+    pub fn push_str(&mut self, text: &str) {
+        self.queue
+            .extend(text.chars().filter(char::is_ascii).map(|c| c as u8));
+    }
+
+    /// Returns the number of bytes waiting
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns true if nothing is waiting
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Remove everything waiting
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+impl Device for Input {
+    fn read(&mut self, offset: u8) -> u8 {
+        match offset {
+            INPUT_DATA => self.queue.pop_front().unwrap_or(0x00),
+            _ => self.peek(offset),
+        }
+    }
+
+    fn peek(&self, offset: u8) -> u8 {
+        match offset {
+            INPUT_DATA => self.queue.front().copied().unwrap_or(0x00),
+            INPUT_STATUS if !self.queue.is_empty() => INPUT_READY,
+            _ => 0x00,
+        }
+    }
+
+    fn write(&mut self, _offset: u8, _value: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vole::{
+        StartMode, Vole,
+        console::{CONSOLE_ADDRESS, CONSOLE_SIZE, Console},
+        debug::StopReason,
+    };
+
+    // Echo input to the console until a newline is read
+    const ECHO: [u8; 20] = [
+        0x20, 0x00, // 0x00: ld r0, 0x00
+        0x11, 0xF3, // 0x02: ld r1, (0xF3)
+        0xB1, 0x02, // 0x04: jp r1, 0x02
+        0x12, 0xF2, // 0x06: ld r2, (0xF2)
+        0x32, 0xF0, // 0x08: ld (0xF0), r2
+        0x20, 0x0A, // 0x0A: ld r0, 0x0A
+        0xB2, 0x12, // 0x0C: jp r2, 0x12
+        0x20, 0x00, // 0x0E: ld r0, 0x00
+        0xB0, 0x02, // 0x10: jp r0, 0x02
+        0xC0, 0x00, // 0x12: halt
+    ];
+
+    fn echo_machine(input: &[u8]) -> Vole {
+        let mut vole = Vole::new();
+        vole.bus_mut()
+            .map(
+                CONSOLE_ADDRESS..=CONSOLE_ADDRESS + CONSOLE_SIZE - 1,
+                Console::new(),
+            )
+            .unwrap();
+        vole.bus_mut()
+            .map(
+                INPUT_ADDRESS..=INPUT_ADDRESS + INPUT_SIZE - 1,
+                Input::with_bytes(input),
+            )
+            .unwrap();
+        vole.load_rom(&ECHO).unwrap();
+        vole.start(&StartMode::Reset, None);
+        vole
+    }
+
+    #[test]
+    fn queue() {
+        let mut input = Input::with_bytes(b"ab");
+        input.push_str("c\u{e9}d");
+        assert_eq!(input.len(), 4);
+
+        assert_eq!(input.peek(INPUT_STATUS), INPUT_READY);
+        assert_eq!(input.peek(INPUT_DATA), b'a');
+        let bytes: Vec<u8> = (0..4).map(|_| input.read(INPUT_DATA)).collect();
+        assert_eq!(bytes, b"abcd");
+
+        assert!(input.is_empty());
+        assert_eq!(input.read(INPUT_STATUS), 0x00);
+        assert_eq!(input.read(INPUT_DATA), 0x00);
+    }
+
+    #[test]
+    fn echo() {
+        let mut vole = echo_machine(b"hello\nignored");
+        assert_eq!(vole.run(1000), StopReason::Halted);
+        assert_eq!(vole.bus().device::<Console>().unwrap().output(), "hello\n");
+        assert_eq!(vole.bus().device::<Input>().unwrap().len(), 7);
+    }
+
+    #[test]
+    fn waits_for_input() {
+        let mut vole = echo_machine(b"");
+        assert_eq!(vole.run(100), StopReason::BudgetExhausted);
+        assert!(vole.bus().device::<Console>().unwrap().output().is_empty());
+
+        vole.bus_mut()
+            .device_mut::<Input>()
+            .unwrap()
+            .push_bytes(b"x\n");
+        assert_eq!(vole.run(100), StopReason::Halted);
+        assert_eq!(vole.bus().device::<Console>().unwrap().output(), "x\n");
+    }
+}
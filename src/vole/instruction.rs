@@ -0,0 +1,341 @@
+//! The Vole instruction set, shared by the machine and the assembler
+
+use std::fmt;
+
+/// A decoded Vole machine instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// `0x1RXY` LOAD register R with the memory cell at XY
+    Load {
+        /// Destination register
+        r: u8,
+        /// Memory address
+        address: u8,
+    },
+
+    /// `0x2RXY` LOAD register R with the bit pattern XY
+    LoadImm {
+        /// Destination register
+        r: u8,
+        /// Value
+        value: u8,
+    },
+
+    /// `0x3RXY` STORE register R into the memory cell at XY
+    Store {
+        /// Source register
+        r: u8,
+        /// Memory address
+        address: u8,
+    },
+
+    /// `0x40RS` MOVE register R into register S
+    Move {
+        /// Source register R
+        src: u8,
+        /// Destination register S
+        dest: u8,
+    },
+
+    /// `0x5RST` ADD registers S and T as two's complement, store in R
+    AddS {
+        /// Destination register
+        r: u8,
+        /// Left operand register
+        s: u8,
+        /// Right operand register
+        t: u8,
+    },
+
+    /// `0x6RST` ADD registers S and T as floating point, store in R
+    AddF {
+        /// Destination register
+        r: u8,
+        /// Left operand register
+        s: u8,
+        /// Right operand register
+        t: u8,
+    },
+
+    /// `0x7RST` OR registers S and T, store in R
+    Or {
+        /// Destination register
+        r: u8,
+        /// Left operand register
+        s: u8,
+        /// Right operand register
+        t: u8,
+    },
+
+    /// `0x8RST` AND registers S and T, store in R
+    And {
+        /// Destination register
+        r: u8,
+        /// Left operand register
+        s: u8,
+        /// Right operand register
+        t: u8,
+    },
+
+    /// `0x9RST` XOR registers S and T, store in R
+    Xor {
+        /// Destination register
+        r: u8,
+        /// Left operand register
+        s: u8,
+        /// Right operand register
+        t: u8,
+    },
+
+    /// `0xAR0X` ROTATE register R to the right X times
+    Rot {
+        /// Register
+        r: u8,
+        /// Number of bits, 0 to 15
+        x: u8,
+    },
+
+    /// `0xBRXY` JUMP to XY if register R equals register 0
+    Jump {
+        /// Register compared with register 0
+        r: u8,
+        /// Target address
+        address: u8,
+    },
+
+    /// `0xC000` HALT execution, any `0xCxxx` decodes as a halt
+    Halt,
+}
+
+impl Instruction {
+    /// Decodes an instruction, returns `None` for the unused op-codes 0x0 and 0xD to 0xF.
+    ///
+    /// Fields the specification marks as zero are ignored.
+    #[must_use]
+    pub const fn decode(ir: u16) -> Option<Self> {
+        let r = ((ir & 0x0F00) >> 8) as u8;
+        let s = ((ir & 0x00F0) >> 4) as u8;
+        let t = (ir & 0x000F) as u8;
+        let xy = (ir & 0x00FF) as u8;
+
+        let instruction = match ir >> 12 {
+            0x1 => Instruction::Load { r, address: xy },
+            0x2 => Instruction::LoadImm { r, value: xy },
+            0x3 => Instruction::Store { r, address: xy },
+            0x4 => Instruction::Move { src: s, dest: t },
+            0x5 => Instruction::AddS { r, s, t },
+            0x6 => Instruction::AddF { r, s, t },
+            0x7 => Instruction::Or { r, s, t },
+            0x8 => Instruction::And { r, s, t },
+            0x9 => Instruction::Xor { r, s, t },
+            0xA => Instruction::Rot { r, x: t },
+            0xB => Instruction::Jump { r, address: xy },
+            0xC => Instruction::Halt,
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// Returns the op-code, the high 4 bits of the encoding
+    #[must_use]
+    pub const fn opcode(&self) -> u8 {
+        match self {
+            Instruction::Load { .. } => 0x1,
+            Instruction::LoadImm { .. } => 0x2,
+            Instruction::Store { .. } => 0x3,
+            Instruction::Move { .. } => 0x4,
+            Instruction::AddS { .. } => 0x5,
+            Instruction::AddF { .. } => 0x6,
+            Instruction::Or { .. } => 0x7,
+            Instruction::And { .. } => 0x8,
+            Instruction::Xor { .. } => 0x9,
+            Instruction::Rot { .. } => 0xA,
+            Instruction::Jump { .. } => 0xB,
+            Instruction::Halt => 0xC,
+        }
+    }
+
+    /// Encodes the instruction, register and rotate fields are masked to 4 bits
+    #[must_use]
+    pub const fn encode(&self) -> u16 {
+        let (a, b, c) = match *self {
+            Instruction::Load { r, address: xy }
+            | Instruction::LoadImm { r, value: xy }
+            | Instruction::Store { r, address: xy }
+            | Instruction::Jump { r, address: xy } => (r, xy >> 4, xy),
+            Instruction::Move { src, dest } => (0, src, dest),
+            Instruction::AddS { r, s, t }
+            | Instruction::AddF { r, s, t }
+            | Instruction::Or { r, s, t }
+            | Instruction::And { r, s, t }
+            | Instruction::Xor { r, s, t } => (r, s, t),
+            Instruction::Rot { r, x } => (r, 0, x),
+            Instruction::Halt => (0, 0, 0),
+        };
+
+        ((self.opcode() as u16) << 12)
+            | (((a & 0xF) as u16) << 8)
+            | (((b & 0xF) as u16) << 4)
+            | ((c & 0xF) as u16)
+    }
+
+    /// Encodes the instruction as the two bytes stored in memory
+    #[must_use]
+    pub const fn to_bytes(&self) -> [u8; 2] {
+        self.encode().to_be_bytes()
+    }
+
+    /// Returns the assembly mnemonic
+    #[must_use]
+    pub const fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Load { .. }
+            | Instruction::LoadImm { .. }
+            | Instruction::Store { .. }
+            | Instruction::Move { .. } => "ld",
+            Instruction::AddS { .. } => "adds",
+            Instruction::AddF { .. } => "addf",
+            Instruction::Or { .. } => "or",
+            Instruction::And { .. } => "and",
+            Instruction::Xor { .. } => "xor",
+            Instruction::Rot { .. } => "rot",
+            Instruction::Jump { .. } => "jp",
+            Instruction::Halt => "halt",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction as assembly, jump targets are written as addresses
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.mnemonic();
+        match *self {
+            Instruction::Load { r, address } => write!(f, "{mnemonic} r{r:x}, (0x{address:02X})"),
+            Instruction::LoadImm { r, value } => write!(f, "{mnemonic} r{r:x}, 0x{value:02X}"),
+            Instruction::Store { r, address } => write!(f, "{mnemonic} (0x{address:02X}), r{r:x}"),
+            Instruction::Move { src, dest } => write!(f, "{mnemonic} r{dest:x}, r{src:x}"),
+            Instruction::AddS { r, s, t }
+            | Instruction::AddF { r, s, t }
+            | Instruction::Or { r, s, t }
+            | Instruction::And { r, s, t }
+            | Instruction::Xor { r, s, t } => write!(f, "{mnemonic} r{r:x}, r{s:x}, r{t:x}"),
+            Instruction::Rot { r, x } => write!(f, "{mnemonic} r{r:x}, 0x{x:02X}"),
+            Instruction::Jump { r, address } => write!(f, "{mnemonic} r{r:x}, 0x{address:02X}"),
+            Instruction::Halt => write!(f, "{mnemonic}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn book_examples() {
+        assert_eq!(
+            Instruction::decode(0x14A3),
+            Some(Instruction::Load {
+                r: 4,
+                address: 0xA3
+            })
+        );
+        assert_eq!(
+            Instruction::decode(0x20A3),
+            Some(Instruction::LoadImm { r: 0, value: 0xA3 })
+        );
+        assert_eq!(
+            Instruction::decode(0x35B1),
+            Some(Instruction::Store {
+                r: 5,
+                address: 0xB1
+            })
+        );
+        assert_eq!(
+            Instruction::decode(0x40A4),
+            Some(Instruction::Move { src: 0xA, dest: 4 })
+        );
+        assert_eq!(
+            Instruction::decode(0x5726),
+            Some(Instruction::AddS { r: 7, s: 2, t: 6 })
+        );
+        assert_eq!(
+            Instruction::decode(0x634E),
+            Some(Instruction::AddF { r: 3, s: 4, t: 0xE })
+        );
+        assert_eq!(
+            Instruction::decode(0xA403),
+            Some(Instruction::Rot { r: 4, x: 3 })
+        );
+        assert_eq!(
+            Instruction::decode(0xB43C),
+            Some(Instruction::Jump {
+                r: 4,
+                address: 0x3C
+            })
+        );
+        assert_eq!(Instruction::decode(0xC000), Some(Instruction::Halt));
+        assert_eq!(Instruction::decode(0xC123), Some(Instruction::Halt));
+    }
+
+    #[test]
+    fn invalid_opcodes() {
+        for ir in [0x0000, 0x0123, 0xD000, 0xEFFF, 0xFFFF] {
+            assert_eq!(Instruction::decode(ir), None);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for ir in 0..=u16::MAX {
+            let Some(instruction) = Instruction::decode(ir) else {
+                continue;
+            };
+
+            // Encodings with non-zero reserved fields decode to the canonical form
+            let encoded = instruction.encode();
+            assert_eq!(Instruction::decode(encoded), Some(instruction));
+
+            let canonical = match ir >> 12 {
+                0x4 => ir & 0xF0FF,
+                0xA => ir & 0xFF0F,
+                0xC => 0xC000,
+                _ => ir,
+            };
+            assert_eq!(encoded, canonical, "{ir:#06X}");
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Instruction::Load {
+                r: 4,
+                address: 0x44
+            }
+            .to_string(),
+            "ld r4, (0x44)"
+        );
+        assert_eq!(
+            Instruction::Store {
+                r: 5,
+                address: 0x46
+            }
+            .to_string(),
+            "ld (0x46), r5"
+        );
+        assert_eq!(
+            Instruction::Move { src: 0xA, dest: 4 }.to_string(),
+            "ld r4, ra"
+        );
+        assert_eq!(
+            Instruction::Xor { r: 0xC, s: 6, t: 7 }.to_string(),
+            "xor rc, r6, r7"
+        );
+        assert_eq!(
+            Instruction::Rot { r: 0xD, x: 2 }.to_string(),
+            "rot rd, 0x02"
+        );
+        assert_eq!(Instruction::Halt.to_string(), "halt");
+    }
+}
@@ -0,0 +1,188 @@
+//! Execution trace recorded by `Vole::cycle`
+
+use super::instruction::Instruction;
+use std::{collections::VecDeque, fmt};
+
+/// A register written during a cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterWrite {
+    /// Register index
+    pub register: u8,
+
+    /// Value before the write
+    pub old: u8,
+
+    /// Value after the write
+    pub new: u8,
+}
+
+/// A memory cell written during a cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    /// Memory address
+    pub address: u8,
+
+    /// Value before the write
+    pub old: u8,
+
+    /// Value after the write
+    pub new: u8,
+}
+
+/// Everything a single cycle did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Program counter before it was incremented
+    pub pc: u8,
+
+    /// Fetched instruction
+    pub ir: u16,
+
+    /// Decoded instruction, `None` if the op-code is invalid
+    pub instruction: Option<Instruction>,
+
+    /// Memory cell loaded by the instruction, if any
+    pub memory_read: Option<u8>,
+
+    /// Register written by the instruction, if any
+    pub register_write: Option<RegisterWrite>,
+
+    /// Memory cell written by the instruction, if any
+    pub memory_write: Option<MemoryWrite>,
+}
+
+impl TraceEntry {
+    /// Creates an entry for the fetched instruction without any writes
+    #[must_use]
+    pub const fn new(pc: u8, ir: u16) -> Self {
+        Self {
+            pc,
+            ir,
+            instruction: Instruction::decode(ir),
+            memory_read: None,
+            register_write: None,
+            memory_write: None,
+        }
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02X}: 0x{:04X}", self.pc, self.ir)?;
+
+        match self.instruction {
+            Some(instruction) => write!(f, "  {instruction}")?,
+            None => write!(f, "  ???")?,
+        }
+
+        if let Some(write) = self.register_write {
+            write!(
+                f,
+                "  r{:X}: 0x{:02X} -> 0x{:02X}",
+                write.register, write.old, write.new
+            )?;
+        }
+
+        if let Some(write) = self.memory_write {
+            write!(
+                f,
+                "  (0x{:02X}): 0x{:02X} -> 0x{:02X}",
+                write.address, write.old, write.new
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Bounded execution trace, the oldest entries are dropped once the capacity is reached
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Trace {
+    /// Create a trace holding at most `capacity` entries
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record an entry, dropping the oldest if the trace is full
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the maximum number of entries
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of recorded entries
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if nothing has been recorded
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the most recent entry
+    #[must_use]
+    pub fn last(&self) -> Option<&TraceEntry> {
+        self.entries.back()
+    }
+
+    /// Iterate over the entries, oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer() {
+        let mut trace = Trace::new(4);
+        for pc in 0..10u8 {
+            trace.push(TraceEntry::new(pc, 0xC000));
+        }
+
+        assert_eq!(trace.len(), 4);
+        let pcs: Vec<u8> = trace.iter().map(|entry| entry.pc).collect();
+        assert_eq!(pcs, [6, 7, 8, 9]);
+        assert_eq!(trace.last().map(|entry| entry.pc), Some(9));
+
+        trace.clear();
+        assert!(trace.is_empty());
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut trace = Trace::new(0);
+        trace.push(TraceEntry::new(0, 0xC000));
+        assert!(trace.is_empty());
+    }
+}